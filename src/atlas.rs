@@ -58,5 +58,229 @@ pub enum Tile {
   Reeds = 52,
   GrassPatch = 53,
   Debug = 54,
+  Cliff = 55,
+  CliffNorth = 56,
+  CliffEast = 57,
+  CliffSouth = 58,
+  CliffWest = 59,
+  CliffNorthEast1 = 60,
+  CliffSouthEast1 = 61,
+  CliffSouthWest1 = 62,
+  CliffNorthWest1 = 63,
+  CliffNorthEast3 = 64,
+  CliffSouthEast3 = 65,
+  CliffSouthWest3 = 66,
+  CliffNorthWest3 = 67,
+  CliffSaddleNorthEast = 68,
+  CliffSaddleSouthEast = 69,
+  Sand = 70,
+  SandNorth = 71,
+  SandEast = 72,
+  SandSouth = 73,
+  SandWest = 74,
+  SandNorthEast1 = 75,
+  SandSouthEast1 = 76,
+  SandSouthWest1 = 77,
+  SandNorthWest1 = 78,
+  SandNorthEast3 = 79,
+  SandSouthEast3 = 80,
+  SandSouthWest3 = 81,
+  SandNorthWest3 = 82,
+  SandSaddleNorthEast = 83,
+  SandSaddleSouthEast = 84,
+  WaterLily = 85,
+  House = 86,
+  BarnWest = 87,
+  BarnEast = 88,
+  HallNorthWest = 89,
+  HallNorthEast = 90,
+  HallSouthWest = 91,
+  HallSouthEast = 92,
+  Ruin = 93,
+  RuinPillar = 94,
+  Tent = 95,
+  Campfire = 96,
+  Shrine = 97,
+  Bridge1 = 98,
+  Bridge2 = 99,
+  BridgeRampNorth = 100,
+  BridgeRampEast = 101,
+  BridgeRampSouth = 102,
+  BridgeRampWest = 103,
+  TunnelNorth = 104,
+  TunnelEast = 105,
+  TunnelSouth = 106,
+  TunnelWest = 107,
+  Tunnel1 = 108,
+  Tunnel2 = 109,
+  Snow = 110,
+  SnowAlt = 111,
+  SnowNorth = 112,
+  SnowEast = 113,
+  SnowSouth = 114,
+  SnowWest = 115,
+  SnowNorthEast1 = 116,
+  SnowSouthEast1 = 117,
+  SnowSouthWest1 = 118,
+  SnowNorthWest1 = 119,
+  SnowNorthEast3 = 120,
+  SnowSouthEast3 = 121,
+  SnowSouthWest3 = 122,
+  SnowNorthWest3 = 123,
+  SnowSaddleNorthEast = 124,
+  SnowSaddleSouthEast = 125,
+  Ice = 126,
+  SnowCliff = 127,
+  SnowCliffNorth = 128,
+  SnowCliffEast = 129,
+  SnowCliffSouth = 130,
+  SnowCliffWest = 131,
+  SnowCliffNorthEast1 = 132,
+  SnowCliffSouthEast1 = 133,
+  SnowCliffSouthWest1 = 134,
+  SnowCliffNorthWest1 = 135,
+  SnowCliffNorthEast3 = 136,
+  SnowCliffSouthEast3 = 137,
+  SnowCliffSouthWest3 = 138,
+  SnowCliffNorthWest3 = 139,
+  SnowCliffSaddleNorthEast = 140,
+  SnowCliffSaddleSouthEast = 141,
   Empty = 255,
 }
+
+// Rock, cliff and water tiles and their snow and ice covered versions. Deep
+// water stays open: there is a single ice tile, and thawing it has to give
+// back the water that was under it, so only shallow water freezes.
+const SNOW_TILES: [(Tile, Tile); 32] = [
+  (Tile::Rock, Tile::Snow),
+  (Tile::RockAlt, Tile::SnowAlt),
+  (Tile::RockNorth, Tile::SnowNorth),
+  (Tile::RockEast, Tile::SnowEast),
+  (Tile::RockSouth, Tile::SnowSouth),
+  (Tile::RockWest, Tile::SnowWest),
+  (Tile::RockNorthEast1, Tile::SnowNorthEast1),
+  (Tile::RockSouthEast1, Tile::SnowSouthEast1),
+  (Tile::RockSouthWest1, Tile::SnowSouthWest1),
+  (Tile::RockNorthWest1, Tile::SnowNorthWest1),
+  (Tile::RockNorthEast3, Tile::SnowNorthEast3),
+  (Tile::RockSouthEast3, Tile::SnowSouthEast3),
+  (Tile::RockSouthWest3, Tile::SnowSouthWest3),
+  (Tile::RockNorthWest3, Tile::SnowNorthWest3),
+  (Tile::RockSaddleNorthEast, Tile::SnowSaddleNorthEast),
+  (Tile::RockSaddleSouthEast, Tile::SnowSaddleSouthEast),
+  (Tile::Cliff, Tile::SnowCliff),
+  (Tile::CliffNorth, Tile::SnowCliffNorth),
+  (Tile::CliffEast, Tile::SnowCliffEast),
+  (Tile::CliffSouth, Tile::SnowCliffSouth),
+  (Tile::CliffWest, Tile::SnowCliffWest),
+  (Tile::CliffNorthEast1, Tile::SnowCliffNorthEast1),
+  (Tile::CliffSouthEast1, Tile::SnowCliffSouthEast1),
+  (Tile::CliffSouthWest1, Tile::SnowCliffSouthWest1),
+  (Tile::CliffNorthWest1, Tile::SnowCliffNorthWest1),
+  (Tile::CliffNorthEast3, Tile::SnowCliffNorthEast3),
+  (Tile::CliffSouthEast3, Tile::SnowCliffSouthEast3),
+  (Tile::CliffSouthWest3, Tile::SnowCliffSouthWest3),
+  (Tile::CliffNorthWest3, Tile::SnowCliffNorthWest3),
+  (Tile::CliffSaddleNorthEast, Tile::SnowCliffSaddleNorthEast),
+  (Tile::CliffSaddleSouthEast, Tile::SnowCliffSaddleSouthEast),
+  (Tile::Water, Tile::Ice),
+];
+
+impl Tile {
+  pub fn is_road(self) -> bool {
+    matches!(
+      self,
+      Tile::Road1
+        | Tile::Road2
+        | Tile::RoadNorthEast
+        | Tile::RoadSouthEast
+        | Tile::RoadSouthWest
+        | Tile::RoadNorthWest
+        | Tile::RoadSlopeNorth
+        | Tile::RoadSlopeEast
+        | Tile::RoadSlopeSouth
+        | Tile::RoadSlopeWest
+    )
+  }
+
+  pub fn is_building(self) -> bool {
+    (Tile::House as u8..=Tile::HallSouthEast as u8).contains(&(self as u8))
+  }
+
+  pub fn is_bridge(self) -> bool {
+    (Tile::Bridge1 as u8..=Tile::BridgeRampWest as u8).contains(&(self as u8))
+  }
+
+  pub fn is_tunnel(self) -> bool {
+    (Tile::TunnelNorth as u8..=Tile::Tunnel2 as u8).contains(&(self as u8))
+  }
+
+  pub fn is_snow(self) -> bool {
+    (Tile::Snow as u8..=Tile::SnowCliffSaddleSouthEast as u8).contains(&(self as u8))
+  }
+
+  /// The snow covered version of a rock or cliff tile, or ice for shallow
+  /// water. Other tiles, deep water included, are returned unchanged.
+  pub fn snowy(self) -> Tile {
+    SNOW_TILES
+      .iter()
+      .find(|&&(tile, _)| tile == self)
+      .map_or(self, |&(_, snowy)| snowy)
+  }
+
+  /// The rock, cliff or water under a snow or ice tile. Other tiles are
+  /// returned unchanged.
+  pub fn thawed(self) -> Tile {
+    SNOW_TILES
+      .iter()
+      .find(|&&(_, snowy)| snowy == self)
+      .map_or(self, |&(tile, _)| tile)
+  }
+
+  /// The terrain a tile belongs to: `Base`, `Marsh`, `Rock`, `Cliff`, `Sand`,
+  /// `Water` or `WaterDeep`. Slopes and edges belong to their flat tile, roads to
+  /// `Base`, and snow and ice to the rock, cliff and water under them.
+  pub fn terrain(self) -> Tile {
+    match self as u8 {
+      0..=4 => Tile::Base,
+      5..=20 => Tile::Marsh,
+      21..=36 => Tile::Rock,
+      39..=48 => Tile::Base,
+      55..=69 => Tile::Cliff,
+      70..=84 => Tile::Sand,
+      110..=125 => Tile::Rock,
+      126 => Tile::Water,
+      127..=141 => Tile::Cliff,
+      _ => self,
+    }
+  }
+
+  /// Visual variants of a tile and their relative weights. Tiles without
+  /// variants return an empty slice.
+  pub fn variants(self) -> &'static [(Tile, u32)] {
+    match self {
+      Tile::Marsh => &[(Tile::Marsh, 3), (Tile::MarshAlt, 1)],
+      Tile::Rock => &[(Tile::Rock, 3), (Tile::RockAlt, 1)],
+      Tile::Snow => &[(Tile::Snow, 3), (Tile::SnowAlt, 1)],
+      _ => &[],
+    }
+  }
+
+  /// Pick one of the tile's variants using `hash`. The same hash always
+  /// picks the same variant.
+  pub fn variant(self, hash: u64) -> Tile {
+    let variants = self.variants();
+    let total: u32 = variants.iter().map(|&(_, weight)| weight).sum();
+    if total == 0 {
+      return self;
+    }
+    let mut pick = (hash % total as u64) as u32;
+    for &(tile, weight) in variants {
+      if pick < weight {
+        return tile;
+      }
+      pick -= weight;
+    }
+    self
+  }
+}
@@ -0,0 +1,198 @@
+use crate::atlas::Tile;
+use crate::map::{cells_between, Map, STEPS};
+
+// Cost of a road cell on land, and of each cell a bridge crosses. Going round
+// water on a grid seldom takes more than a few extra cells, so a bridge that
+// cost more than the road would almost never be built.
+const ROAD_COST: usize = 1;
+const BRIDGE_COST: usize = 1;
+
+fn runs_north_south(tile: Tile) -> bool {
+  matches!(tile, Tile::BridgeRampNorth | Tile::BridgeRampSouth | Tile::Bridge1)
+}
+
+impl Map {
+  // Flat ground a bridge can start or end on
+  fn is_bridge_end(&self, row: usize, col: usize, height: u8) -> bool {
+    matches!(self.get_tile(row, col), Tile::Base | Tile::Sand) && self.get_height(row, col) == height
+  }
+
+  // Water, marsh and the beach sloping down to the water, with nothing on
+  // it and no higher than the bridge
+  fn can_bridge(&self, row: usize, col: usize, height: u8) -> bool {
+    let tile = self.get_tile(row, col);
+    let can_cross = match tile.terrain() {
+      Tile::Water | Tile::WaterDeep | Tile::Marsh => true,
+      Tile::Sand => tile != Tile::Sand,
+      _ => false,
+    };
+    can_cross && self.get_height(row, col) <= height && self.get_tile_2(row, col) == Tile::Empty
+  }
+
+  // The land a bridge going `step` from a cell would reach, and how many
+  // cells it crosses. Bridges cross at least one and at most `bridge_span`
+  // cells of water, and a shore cell on either side.
+  fn bridge_end(&self, row: usize, col: usize, step: (isize, isize)) -> Option<((usize, usize), usize)> {
+    let height = self.get_height(row, col);
+    if !self.is_bridge_end(row, col, height) {
+      return None;
+    }
+    let mut water = 0;
+    for span in 1..=self.config.bridge_span + 3 {
+      let (r, c) = (row as isize + step.0 * span as isize, col as isize + step.1 * span as isize);
+      if !self.in_bounds(r, c) {
+        return None;
+      }
+      let (r, c) = (r as usize, c as usize);
+      if water > 0 && self.is_bridge_end(r, c, height) {
+        return Some(((r, c), span - 1));
+      }
+      if !self.can_bridge(r, c, height) {
+        return None;
+      }
+      if matches!(self.get_tile(r, c), Tile::Water | Tile::WaterDeep) {
+        water += 1;
+      }
+      if water > self.config.bridge_span || span - water > 2 {
+        return None;
+      }
+    }
+    None
+  }
+
+  /// Cells a road can go to next from a cell, with the cost of going there:
+  /// walkable neighbors, and land across a short bridge or through a tunnel
+  pub(crate) fn road_cells(&self, row: usize, col: usize) -> Vec<((usize, usize), usize)> {
+    let mut cells = self
+      .walkable_cells(row, col)
+      .into_iter()
+      .map(|cell| (cell, ROAD_COST))
+      .collect::<Vec<((usize, usize), usize)>>();
+    for &step in STEPS.iter() {
+      if let Some((end, span)) = self.bridge_end(row, col, step) {
+        cells.push((end, ROAD_COST + span * BRIDGE_COST));
+      }
+    }
+    cells.extend(self.tunnel_cells(row, col));
+    cells
+  }
+
+  // Bridge tiles on the cells between the two ends of a bridge, with ramps
+  // down to the ends. The water stays underneath.
+  pub(crate) fn build_bridge(&mut self, start: (usize, usize), end: (usize, usize)) {
+    let north_south = start.1 == end.1;
+    let cells = cells_between(start, end);
+    // A single cell has no room for ramps
+    let has_ramps = cells.len() > 1;
+    for (i, &(row, col)) in cells.iter().enumerate() {
+      let tile = match (north_south, has_ramps && i == 0, has_ramps && i + 1 == cells.len()) {
+        (true, true, _) => Tile::BridgeRampNorth,
+        (true, _, true) => Tile::BridgeRampSouth,
+        (true, _, _) => Tile::Bridge1,
+        (false, true, _) => Tile::BridgeRampWest,
+        (false, _, true) => Tile::BridgeRampEast,
+        (false, _, _) => Tile::Bridge2,
+      };
+      self.set_tile_2(row, col, tile);
+    }
+  }
+
+  /// Whether two neighboring cells are joined by a bridge: both are bridge
+  /// cells or ground at an end, in a line along the bridge
+  pub(crate) fn is_bridge_link(&self, a: (usize, usize), b: (usize, usize)) -> bool {
+    let north_south = a.1 == b.1;
+    let is_end = |(row, col): (usize, usize)| {
+      let tile_2 = self.get_tile_2(row, col);
+      let tile = self.get_tile(row, col);
+      if tile_2.is_bridge() {
+        runs_north_south(tile_2) == north_south
+      } else {
+        matches!(tile, Tile::Base | Tile::Sand) || tile.is_road()
+      }
+    };
+    (self.get_tile_2(a.0, a.1).is_bridge() || self.get_tile_2(b.0, b.1).is_bridge()) && is_end(a) && is_end(b)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::atlas::Tile;
+  use crate::config::GenerationMode;
+  use crate::game::Game;
+  use crate::map::Map;
+  use crate::shape::Shape;
+
+  // Land at height 2 with a river at height 1 `width` cells wide down the
+  // middle, and beaches sloping down to it
+  fn river_map(width: usize) -> Map {
+    let mut map = Map::new(8, 16);
+    map.height_map = vec![2; 8 * 16];
+    for row in 0..8 {
+      for col in 8 - width / 2..8 - width / 2 + width {
+        map.set_tile(row, col, Tile::Water);
+        map.set_height(row, col, 1);
+      }
+      map.set_tile(row, 7 - width / 2, Tile::SandEast);
+      map.set_tile(row, 8 - width / 2 + width, Tile::SandWest);
+    }
+    map
+  }
+
+  #[test]
+  fn roads_bridge_narrow_water() {
+    // Two cells of water and a beach on either side
+    let map = river_map(2);
+    assert!(map.road_cells(3, 5).contains(&((3, 10), 5)));
+
+    // Too wide to bridge
+    let map = river_map(6);
+    assert!(map.road_cells(3, 3).iter().all(|&((_, col), _)| col < 4));
+  }
+
+  #[test]
+  fn bridges_keep_the_water() {
+    let mut map = river_map(2);
+    map.set_tile_2(3, 0, Tile::Rocks);
+    map.set_tile_2(3, 15, Tile::Rocks);
+    let path = (0..=5)
+      .map(|col| (3, col))
+      .chain((10..=15).map(|col| (3, col)))
+      .collect::<Vec<(usize, usize)>>();
+    map.lay_road(&path);
+    assert_eq!(map.get_tile(3, 7), Tile::Water);
+    let bridge = (6..10).map(|col| map.get_tile_2(3, col)).collect::<Vec<Tile>>();
+    assert_eq!(
+      bridge,
+      vec![Tile::BridgeRampWest, Tile::Bridge2, Tile::Bridge2, Tile::BridgeRampEast]
+    );
+    assert_eq!(map.validate(), vec![]);
+  }
+
+  #[test]
+  fn bridges_can_be_walked() {
+    let mut map = river_map(2);
+    map.set_tile_2(3, 0, Tile::Rocks);
+    map.set_tile_2(3, 15, Tile::Rocks);
+    let path = (0..=5)
+      .map(|col| (3, col))
+      .chain((10..=15).map(|col| (3, col)))
+      .collect::<Vec<(usize, usize)>>();
+    map.lay_road(&path);
+    assert!(map.walkable_cells(3, 5).contains(&(3, 6)));
+    assert_eq!(map.walkable_cells(3, 7), vec![(3, 6), (3, 8)]);
+    assert!(map.walkable_cells(3, 9).contains(&(3, 10)));
+    // Not on or off the sides
+    assert!(!map.walkable_cells(2, 7).contains(&(3, 7)));
+    assert!(!map.walkable_cells(2, 6).contains(&(3, 6)));
+  }
+
+  #[test]
+  fn generated_roads_bridge_water() {
+    // Seeds whose roads cross water
+    for &(seed, size) in [("18", 64), ("20", 128)].iter() {
+      let game = Game::new(size, size, seed, Shape::Open, GenerationMode::Noise);
+      assert!(game.tile_map_2().iter().any(|tile| tile.is_bridge()), "No bridges for seed {}", seed);
+      assert_eq!(game.js_validate(), Vec::<String>::new());
+    }
+  }
+}
@@ -0,0 +1,648 @@
+use crate::atlas::Tile;
+use crate::config::GenerationConfig;
+use crate::map::{edge_slope, is_base_slope, slope_tile, Map, Slope};
+use crate::utils::{derive_seed, warn};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+// Weight of every slope piece, next to 20 for flat ground. Higher gives
+// rougher ground.
+const SLOPE_WEIGHT: f64 = 0.5;
+// Choices undone one by one after a contradiction, before starting over
+// around it
+const BACKTRACK_DEPTH: usize = 8;
+// Cells around a contradiction that are started over, growing while the
+// contradictions keep coming
+const RESET_RADIUS: usize = 2;
+const CALM_CHOICES: usize = 64;
+// Contradictions per cell before the solver gives up
+const CONTRADICTIONS_PER_CELL: usize = 4;
+// Seeds tried before falling back to flat ground
+const ATTEMPTS: usize = 3;
+
+// Sides go north, east, south, west and corners north west, north east,
+// south east, south west. Side `i` runs from corner `i` to corner `i + 1`.
+
+/// A tile at a height, with the heights along its sides and at its corners
+#[derive(Clone, Copy, Debug)]
+struct Piece {
+  tile: Tile,
+  // What stands on the tile
+  tile_2: Tile,
+  height: u8,
+  // Whether each side and corner is level with the cell, otherwise it is one
+  // level lower
+  sides: [bool; 4],
+  corners: [bool; 4],
+  // Sides a road leaves through
+  roads: [bool; 4],
+  weight: f64,
+}
+
+impl Piece {
+  fn side_height(&self, side: usize) -> u8 {
+    self.height - !self.sides[side] as u8
+  }
+
+  fn corner_height(&self, corner: usize) -> u8 {
+    self.height - !self.corners[corner] as u8
+  }
+
+  // A lower corner between two level sides, only the cell diagonally across
+  // can be lower
+  fn is_pit(&self, corner: usize) -> bool {
+    !self.corners[corner] && self.sides[corner] && self.sides[(corner + 3) % 4]
+  }
+
+  fn is_water(&self) -> bool {
+    matches!(self.tile, Tile::Water | Tile::WaterDeep)
+  }
+
+  fn is_shore(&self) -> bool {
+    matches!(self.tile.terrain(), Tile::Marsh | Tile::Sand)
+  }
+
+  // Whether `other` can be the neighbor on `side`. Shared sides and corners
+  // must be at the same height, and a side is only lower where it drops to
+  // the lower of the two cells.
+  fn fits(&self, other: &Piece, side: usize) -> bool {
+    let opposite = (side + 2) % 4;
+    let corners = [(side, (side + 3) % 4), ((side + 1) % 4, (side + 2) % 4)];
+    self.side_height(side) == other.side_height(opposite)
+      && self.side_height(side) == self.height.min(other.height)
+      && corners.iter().all(|&(a, b)| {
+        self.corner_height(a) == other.corner_height(b) && !(self.is_pit(a) && other.is_pit(b))
+      })
+      && self.roads[side] == other.roads[opposite]
+      && (self.is_water() == other.is_water() || self.is_shore() || other.is_shore())
+  }
+}
+
+// Every way a cell can be one level above some of its neighbors, as the slope
+// and which sides and corners stay level. `None` is flat ground.
+fn shapes() -> Vec<(Option<Slope>, [bool; 4], [bool; 4])> {
+  // Neighbors clockwise from the north, like `Map::neighbors_8`
+  let offsets = [(0, 1), (0, 2), (1, 2), (2, 2), (2, 1), (2, 0), (1, 0), (0, 0)];
+  let mut shapes: Vec<(Option<Slope>, [bool; 4], [bool; 4])> = Vec::new();
+  for lower in 0..256u32 {
+    let is_lower = |i: usize| lower & (1 << (i % 8)) != 0;
+    let sides = [0, 1, 2, 3].map(|side| !is_lower(side * 2));
+    let corners = [0, 1, 2, 3].map(|corner| (0..3).all(|i| !is_lower(corner * 2 + 6 + i)));
+    let slope = if lower == 0 {
+      None
+    } else {
+      let neighbors = (0..8)
+        .filter(|&i| is_lower(i))
+        .map(|i| offsets[i])
+        .collect::<Vec<(usize, usize)>>();
+      match edge_slope((1, 1), &neighbors) {
+        Slope::Unknown => continue,
+        slope => Some(slope),
+      }
+    };
+    // Lower diagonals next to a lower side don't change the shape
+    if !shapes.iter().any(|s| s.1 == sides && s.2 == corners) {
+      shapes.push((slope, sides, corners));
+    }
+  }
+  shapes
+}
+
+// Every piece the config allows, terrain at the heights its band covers
+fn pieces(config: &GenerationConfig) -> Vec<Piece> {
+  let mut water_heights = config.tile_heights(Tile::Water);
+  water_heights.extend(config.tile_heights(Tile::WaterDeep));
+  water_heights.sort_unstable();
+  water_heights.dedup();
+  // Beaches are one level above the water
+  let sand_heights = water_heights
+    .iter()
+    .map(|height| height + 1)
+    .filter(|&height| height <= config.height_levels)
+    .collect::<Vec<u8>>();
+  let base_heights = config.tile_heights(Tile::Base);
+  let terrain = [
+    (Tile::Base, base_heights.clone(), 20.0),
+    (Tile::Marsh, config.tile_heights(Tile::Marsh), 2.0),
+    (Tile::Rock, config.tile_heights(Tile::Rock), 4.0),
+    (Tile::Sand, sand_heights, 1.0),
+  ];
+
+  let mut pieces = Vec::new();
+  let mut piece = |tile, tile_2, height, sides, corners, roads, weight| {
+    pieces.push(Piece {
+      tile,
+      tile_2,
+      height,
+      sides,
+      corners,
+      roads,
+      weight,
+    })
+  };
+  let shapes = shapes();
+  for &(slope, sides, corners) in shapes.iter() {
+    for (kind, heights, weight) in terrain.iter() {
+      let tile = slope.map_or(*kind, |slope| slope_tile(*kind, slope));
+      // Base only slopes north, east, south or west
+      if slope.is_some() && *kind == Tile::Base && !is_base_slope(tile) {
+        continue;
+      }
+      let weight = if slope.is_some() { SLOPE_WEIGHT } else { *weight };
+      for &height in heights.iter().filter(|&&height| slope.is_none() || height > 1) {
+        piece(tile, Tile::Empty, height, sides, corners, [false; 4], weight);
+      }
+    }
+  }
+  for &height in water_heights.iter() {
+    piece(Tile::Water, Tile::Empty, height, [true; 4], [true; 4], [false; 4], 1.0);
+    piece(Tile::WaterDeep, Tile::Empty, height, [true; 4], [true; 4], [false; 4], 0.5);
+  }
+
+  // Road corners are named after the corner opposite the sides they join
+  let roads = [
+    (Tile::Road1, [true, false, true, false], 0.1),
+    (Tile::Road2, [false, true, false, true], 0.1),
+    (Tile::RoadNorthEast, [false, false, true, true], 0.05),
+    (Tile::RoadSouthEast, [true, false, false, true], 0.05),
+    (Tile::RoadSouthWest, [true, true, false, false], 0.05),
+    (Tile::RoadNorthWest, [false, true, true, false], 0.05),
+  ];
+  let road_slopes = [
+    (Slope::North, Tile::RoadSlopeNorth, [true, false, true, false]),
+    (Slope::East, Tile::RoadSlopeEast, [false, true, false, true]),
+    (Slope::South, Tile::RoadSlopeSouth, [true, false, true, false]),
+    (Slope::West, Tile::RoadSlopeWest, [false, true, false, true]),
+  ];
+  for &height in base_heights.iter() {
+    for &(tile, sides, weight) in roads.iter() {
+      piece(tile, Tile::Empty, height, [true; 4], [true; 4], sides, weight);
+    }
+    // Roads can also end at a landmark
+    for side in 0..4 {
+      let mut sides = [false; 4];
+      sides[side] = true;
+      piece(Tile::Base, Tile::Rocks, height, [true; 4], [true; 4], sides, 0.02);
+    }
+    for &(slope, tile, sides) in road_slopes.iter().filter(|_| height > 1) {
+      if let Some(&(_, level, corners)) = shapes.iter().find(|s| s.0 == Some(slope)) {
+        piece(tile, Tile::Empty, height, level, corners, sides, 0.05);
+      }
+    }
+  }
+  pieces
+}
+
+/// Wave function collapse over tiles and heights. Every cell starts out able
+/// to hold any piece. The cell with the least uncertain choice of pieces is
+/// collapsed to one of them, and pieces that no longer fit are ruled out of the
+/// cells around it. Choices that leave a cell without pieces are undone.
+pub struct Collapse {
+  row_count: usize,
+  col_count: usize,
+  pieces: Vec<Piece>,
+  // Bit set words per cell
+  words: usize,
+  // For each piece and side, the pieces that fit there
+  fits: Vec<u64>,
+  // Pieces each cell can hold before anything is chosen
+  initial: Vec<u64>,
+  // Pieces each cell can still hold
+  cells: Vec<u64>,
+  // Weighted entropy of the pieces left in each cell, scaled to an integer.
+  // `u64::MAX` once a single piece is left.
+  entropy: Vec<u64>,
+  // Cells with more than one piece left, least uncertain first and ties in a
+  // random order. Entries for cells that have changed since are skipped.
+  open: BinaryHeap<Reverse<(u64, usize, usize)>>,
+  rank: Vec<usize>,
+  // Cells as they were before each change, to undo choices
+  trail: Vec<(usize, Vec<u64>)>,
+}
+
+impl Collapse {
+  pub fn new(row_count: usize, col_count: usize, config: &GenerationConfig) -> Collapse {
+    let pieces = pieces(config);
+    let words = pieces.len().div_ceil(64);
+    let mut fits = vec![0; pieces.len() * 4 * words];
+    for (a, piece) in pieces.iter().enumerate() {
+      for side in 0..4 {
+        for (b, other) in pieces.iter().enumerate() {
+          if piece.fits(other, side) {
+            fits[(a * 4 + side) * words + b / 64] |= 1 << (b % 64);
+          }
+        }
+      }
+    }
+
+    let mut collapse = Collapse {
+      row_count,
+      col_count,
+      pieces,
+      words,
+      fits,
+      initial: Vec::new(),
+      cells: Vec::new(),
+      entropy: Vec::new(),
+      open: BinaryHeap::new(),
+      rank: Vec::new(),
+      trail: Vec::new(),
+    };
+    // Nothing leads off the map, so sides along the border stay level
+    let mut cells = Vec::with_capacity(row_count * col_count * words);
+    for row in 0..row_count {
+      for col in 0..col_count {
+        let border = [row == 0, col + 1 == col_count, row + 1 == row_count, col == 0];
+        cells.extend(collapse.bits(|piece| {
+          (0..4).all(|side| {
+            !border[side]
+              || (piece.sides[side]
+                && !piece.roads[side]
+                && !piece.is_pit(side)
+                && !piece.is_pit((side + 1) % 4))
+          })
+        }));
+      }
+    }
+    collapse.initial = cells;
+    collapse
+  }
+
+  fn bits<T: Fn(&Piece) -> bool>(&self, include: T) -> Vec<u64> {
+    let mut bits = vec![0; self.words];
+    for (i, piece) in self.pieces.iter().enumerate() {
+      if include(piece) {
+        bits[i / 64] |= 1 << (i % 64);
+      }
+    }
+    bits
+  }
+
+  fn options(&self, cell: usize) -> Vec<usize> {
+    let mut options = Vec::new();
+    for (i, &word) in self.cells[cell * self.words..(cell + 1) * self.words].iter().enumerate() {
+      let mut bits = word;
+      while bits != 0 {
+        options.push(i * 64 + bits.trailing_zeros() as usize);
+        bits &= bits - 1;
+      }
+    }
+    options
+  }
+
+  fn write(&mut self, cell: usize, bits: &[u64]) {
+    self.cells[cell * self.words..(cell + 1) * self.words].copy_from_slice(bits);
+    let options = self.options(cell);
+    self.entropy[cell] = u64::MAX;
+    if options.len() > 1 {
+      let total = options.iter().map(|&piece| self.pieces[piece].weight).sum::<f64>();
+      let sum = options
+        .iter()
+        .map(|&piece| self.pieces[piece].weight * self.pieces[piece].weight.ln())
+        .sum::<f64>();
+      let entropy = ((total.ln() - sum / total).max(0.0) * 1e9) as u64;
+      self.entropy[cell] = entropy;
+      self.open.push(Reverse((entropy, self.rank[cell], cell)));
+    }
+  }
+
+  // Replace the pieces a cell can hold, so it can be undone
+  fn set(&mut self, cell: usize, bits: Vec<u64>) {
+    let old = self.cells[cell * self.words..(cell + 1) * self.words].to_vec();
+    if bits != old {
+      self.write(cell, &bits);
+      self.trail.push((cell, old));
+    }
+  }
+
+  // Keep only the pieces in `bits`. Returns false if none are left.
+  fn restrict(&mut self, cell: usize, bits: &[u64]) -> bool {
+    let new = self.cells[cell * self.words..(cell + 1) * self.words]
+      .iter()
+      .zip(bits)
+      .map(|(a, b)| a & b)
+      .collect::<Vec<u64>>();
+    let is_empty = new.iter().all(|&word| word == 0);
+    self.set(cell, new);
+    !is_empty
+  }
+
+  // Go back to how the cells were when the trail was `length` long
+  fn undo(&mut self, length: usize) {
+    while self.trail.len() > length {
+      if let Some((cell, old)) = self.trail.pop() {
+        self.write(cell, &old);
+      }
+    }
+  }
+
+  // The undecided cell whose piece is most certain
+  fn next_cell(&mut self) -> Option<usize> {
+    while let Some(Reverse((entropy, _, cell))) = self.open.pop() {
+      if self.entropy[cell] == entropy {
+        return Some(cell);
+      }
+    }
+    None
+  }
+
+  // Rule out pieces that don't fit next to anything the neighboring cell can
+  // still hold, spreading out from the changed cells. Fails with the cell
+  // that has no pieces left on a contradiction.
+  fn propagate(&mut self, mut queue: VecDeque<usize>) -> Result<(), usize> {
+    while let Some(cell) = queue.pop_front() {
+      let (row, col) = (cell / self.col_count, cell % self.col_count);
+      let neighbors = [
+        (row > 0).then(|| cell - self.col_count),
+        (col + 1 < self.col_count).then(|| cell + 1),
+        (row + 1 < self.row_count).then(|| cell + self.col_count),
+        (col > 0).then(|| cell - 1),
+      ];
+      let options = self.options(cell);
+      for (side, neighbor) in neighbors.iter().enumerate() {
+        let neighbor = match neighbor {
+          Some(neighbor) => *neighbor,
+          None => continue,
+        };
+        let mut allowed = vec![0; self.words];
+        for &piece in options.iter() {
+          let start = (piece * 4 + side) * self.words;
+          for (word, fits) in allowed.iter_mut().zip(&self.fits[start..start + self.words]) {
+            *word |= fits;
+          }
+        }
+        let length = self.trail.len();
+        if !self.restrict(neighbor, &allowed) {
+          return Err(neighbor);
+        }
+        if self.trail.len() > length {
+          queue.push_back(neighbor);
+        }
+      }
+    }
+    Ok(())
+  }
+
+  // Forget the pieces chosen around a cell, so they can be chosen again to fit
+  // the cells further out
+  fn reset(&mut self, cell: usize, radius: usize) -> Result<(), usize> {
+    let (row, col) = (cell / self.col_count, cell % self.col_count);
+    let rows = row.saturating_sub(radius)..(row + radius + 1).min(self.row_count);
+    let cols = col.saturating_sub(radius)..(col + radius + 1).min(self.col_count);
+    // The area and the cells around it, which decide what fits inside
+    let mut queue = VecDeque::new();
+    for r in rows.start.saturating_sub(1)..(rows.end + 1).min(self.row_count) {
+      for c in cols.start.saturating_sub(1)..(cols.end + 1).min(self.col_count) {
+        let i = r * self.col_count + c;
+        if rows.contains(&r) && cols.contains(&c) {
+          self.set(i, self.initial[i * self.words..(i + 1) * self.words].to_vec());
+        }
+        queue.push_back(i);
+      }
+    }
+    self.propagate(queue)
+  }
+
+  /// Only allow `tile` at `height` in a cell. Variants of a tile count as the
+  /// tile itself.
+  pub fn fix(&mut self, row: usize, col: usize, tile: Tile, height: u8) -> Result<(), String> {
+    let bits = self.bits(|piece| {
+      piece.height == height && (piece.tile == tile || piece.tile.variants().iter().any(|v| v.0 == tile))
+    });
+    if bits.iter().all(|&word| word == 0) {
+      return Err(format!("{:?} can't be placed at height {}", tile, height));
+    }
+    let cell = row * self.col_count + col;
+    for (word, bits) in self.initial[cell * self.words..(cell + 1) * self.words].iter_mut().zip(bits) {
+      *word &= bits;
+    }
+    Ok(())
+  }
+
+  /// Collapse every cell, returning the tile, the tile standing on it and the
+  /// height of each one
+  pub fn solve(&mut self, rng: &mut StdRng) -> Result<Vec<(Tile, Tile, u8)>, String> {
+    let size = self.row_count * self.col_count;
+    let mut order = (0..size).collect::<Vec<usize>>();
+    order.shuffle(rng);
+    self.rank = vec![0; size];
+    for (i, &cell) in order.iter().enumerate() {
+      self.rank[cell] = i;
+    }
+    self.cells = self.initial.clone();
+    self.entropy = vec![u64::MAX; size];
+    self.open.clear();
+    for cell in 0..size {
+      let bits = self.initial[cell * self.words..(cell + 1) * self.words].to_vec();
+      self.write(cell, &bits);
+    }
+    if self.propagate((0..size).collect()).is_err() {
+      return Err("The fixed cells don't fit together".to_string());
+    }
+    self.trail.clear();
+
+    // Cell, piece and the length of the trail before each choice
+    let mut choices: Vec<(usize, usize, usize)> = Vec::new();
+    let mut contradictions = 0;
+    // Choices undone and the size of the area started over, since the last
+    // calm stretch without contradictions
+    let mut depth = 0;
+    let mut radius = RESET_RADIUS;
+    let mut calm = 0;
+    while let Some(cell) = self.next_cell() {
+      let piece = self.choose(cell, rng);
+      choices.push((cell, piece, self.trail.len()));
+      let mut bits = vec![0; self.words];
+      bits[piece / 64] |= 1 << (piece % 64);
+      self.restrict(cell, &bits);
+      let mut result = self.propagate(VecDeque::from(vec![cell]));
+
+      calm += 1;
+      if calm > CALM_CHOICES {
+        depth = 0;
+        radius = RESET_RADIUS;
+      }
+      while let Err(empty) = result {
+        contradictions += 1;
+        calm = 0;
+        if contradictions > size * CONTRADICTIONS_PER_CELL {
+          return Err("Too many contradictions".to_string());
+        }
+        // Back to before the last choice, or before the last reset if there
+        // are no choices since
+        let choice = choices.pop();
+        self.undo(choice.map_or(0, |(_, _, length)| length));
+        result = match choice {
+          Some((cell, piece, _)) if depth < BACKTRACK_DEPTH => {
+            depth += 1;
+            // Rule out the piece that led to the contradiction
+            let mut bits = vec![u64::MAX; self.words];
+            bits[piece / 64] &= !(1 << (piece % 64));
+            if self.restrict(cell, &bits) {
+              self.propagate(VecDeque::from(vec![cell]))
+            } else {
+              Err(cell)
+            }
+          }
+          // The cause is further back than the last few choices, start over
+          // on a growing area around the contradiction instead
+          _ => {
+            choices.clear();
+            self.trail.clear();
+            radius += 1;
+            self.reset(empty, radius - 1)
+          }
+        };
+      }
+    }
+
+    Ok(
+      (0..size)
+        .map(|cell| {
+          let piece = &self.pieces[self.options(cell)[0]];
+          (piece.tile, piece.tile_2, piece.height)
+        })
+        .collect(),
+    )
+  }
+
+  // Pick one of the cell's pieces, flat ground is more likely than slopes
+  fn choose(&self, cell: usize, rng: &mut StdRng) -> usize {
+    let options = self.options(cell);
+    let total = options.iter().map(|&piece| self.pieces[piece].weight).sum::<f64>();
+    let mut pick = rng.gen_range(0.0..total);
+    for &piece in options.iter() {
+      let weight = self.pieces[piece].weight;
+      if pick < weight {
+        return piece;
+      }
+      pick -= weight;
+    }
+    options[options.len() - 1]
+  }
+}
+
+impl Map {
+  /// Fill the map using wave function collapse. Cells marked in `fixed` keep
+  /// their tile and height, and the rest of the map is made to fit around
+  /// them.
+  pub fn collapse(&mut self, seed: u64, fixed: &[bool]) -> Result<(), String> {
+    let mut collapse = Collapse::new(self.row_count, self.col_count, &self.config);
+    for i in (0..self.tile_map.len()).filter(|&i| fixed[i]) {
+      collapse.fix(i / self.col_count, i % self.col_count, self.tile_map[i], self.height_map[i])?;
+    }
+    let cells = collapse.solve(&mut StdRng::seed_from_u64(seed))?;
+    for (i, (tile, tile_2, height)) in cells.into_iter().enumerate() {
+      self.tile_map[i] = tile;
+      self.height_map[i] = height;
+      // Fixed cells keep what stands on them
+      if !fixed[i] || tile_2 != Tile::Empty {
+        self.tile_map_2[i] = tile_2;
+      }
+    }
+    self.clear_dead_roads(fixed);
+    Ok(())
+  }
+
+  // Tiles only match their neighbors, so roads can close into loops or run
+  // from a landmark back to itself. Turn the ones that don't link two
+  // landmarks back into ground, unless they were fixed.
+  fn clear_dead_roads(&mut self, fixed: &[bool]) {
+    for (cells, destinations) in self.road_networks() {
+      if destinations >= 2 || cells.iter().any(|&(row, col)| fixed[row * self.col_count + col]) {
+        continue;
+      }
+      for (row, col) in cells {
+        let ground = match self.get_tile(row, col) {
+          Tile::RoadSlopeNorth => Tile::BaseNorth,
+          Tile::RoadSlopeEast => Tile::BaseEast,
+          Tile::RoadSlopeSouth => Tile::BaseSouth,
+          Tile::RoadSlopeWest => Tile::BaseWest,
+          tile if tile.is_road() => Tile::Base,
+          _ => continue,
+        };
+        self.set_tile(row, col, ground);
+      }
+    }
+  }
+
+  // Terrain from wave function collapse instead of noise. If no seed gives a
+  // solution the map is left as flat ground, and the last error is logged.
+  pub(crate) fn generate_collapse(&mut self, seed: u64) {
+    let fixed = vec![false; self.tile_map.len()];
+    let mut result = Ok(());
+    for attempt in 0..ATTEMPTS {
+      result = self.collapse(derive_seed(seed, &format!("collapse-{}", attempt)), &fixed);
+      if result.is_ok() {
+        break;
+      }
+    }
+    if let Err(error) = result {
+      warn(&format!("Collapse failed after {} attempts, using flat ground: {}", ATTEMPTS, error));
+      let height = self.config.tile_heights(Tile::Base).first().copied().unwrap_or(1);
+      self.tile_map = vec![Tile::Base; self.tile_map.len()];
+      self.height_map = vec![height; self.tile_map.len()];
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Collapse;
+  use crate::atlas::Tile;
+  use crate::config::GenerationConfig;
+  use crate::map::Map;
+  use rand::rngs::StdRng;
+  use rand::SeedableRng;
+
+  #[test]
+  fn collapsed_maps_are_valid() {
+    let mut map = Map::new(24, 24);
+    map.collapse(1, &vec![false; 24 * 24]).unwrap();
+    assert_eq!(map.validate(), vec![]);
+    // Not just flat ground
+    assert!(map.tile_map.iter().any(|&tile| tile != Tile::Base));
+    assert!(map.height_map.iter().any(|&height| height != map.height_map[0]));
+
+    let mut other = Map::new(24, 24);
+    other.collapse(1, &vec![false; 24 * 24]).unwrap();
+    assert_eq!(map.tile_map, other.tile_map);
+    assert_eq!(map.height_map, other.height_map);
+  }
+
+  #[test]
+  fn completes_fixed_cells() {
+    let mut map = Map::new(16, 16);
+    let mut fixed = vec![false; 16 * 16];
+    for &(row, col, tile, height) in [(3, 3, Tile::Water, 1), (12, 12, Tile::Rock, 4), (8, 4, Tile::Road2, 3)].iter() {
+      map.set_tile(row, col, tile);
+      map.set_height(row, col, height);
+      fixed[row * 16 + col] = true;
+    }
+    map.collapse(7, &fixed).unwrap();
+    assert_eq!(map.get_tile(3, 3), Tile::Water);
+    assert_eq!(map.get_tile(12, 12), Tile::Rock);
+    assert_eq!(map.get_height(12, 12), 4);
+    assert_eq!(map.get_tile(8, 4), Tile::Road2);
+    // The road carries on, or ends at a landmark
+    for col in [3, 5].iter() {
+      assert!(map.get_tile(8, *col).is_road() || map.get_tile_2(8, *col) == Tile::Rocks);
+    }
+    assert_eq!(map.validate(), vec![]);
+  }
+
+  #[test]
+  fn rejects_cells_that_cant_fit() {
+    let config = GenerationConfig::default();
+    let mut collapse = Collapse::new(4, 4, &config);
+    // Rock is never generated down at the water
+    assert!(collapse.fix(0, 0, Tile::Rock, 1).is_err());
+    // Water right next to a mountain top
+    collapse.fix(1, 1, Tile::Water, 1).unwrap();
+    collapse.fix(1, 2, Tile::Rock, 4).unwrap();
+    assert!(collapse.solve(&mut StdRng::seed_from_u64(1)).is_err());
+  }
+}
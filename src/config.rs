@@ -0,0 +1,605 @@
+use crate::atlas::Tile;
+use crate::sampler::{Fractal, NoiseBasis};
+use crate::shape::Shape;
+use wasm_bindgen::prelude::*;
+
+/// Elevations up to `max` (exclusive) become `tile`. Elevations above the last
+/// band use the last band.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainBand {
+  pub max: f64,
+  // One of `WaterDeep`, `Water`, `Marsh`, `Base` or `Rock`
+  pub tile: Tile,
+}
+
+// Short names used in world codes, from the lowest terrain to the highest
+const BAND_TILES: [(char, Tile); 5] = [
+  ('d', Tile::WaterDeep),
+  ('w', Tile::Water),
+  ('m', Tile::Marsh),
+  ('b', Tile::Base),
+  ('r', Tile::Rock),
+];
+
+// Upper bounds on settings read from world codes, so a crafted code can't
+// make generation take forever
+const MAX_OCTAVES: usize = 8;
+const MAX_EROSION_ITERATIONS: usize = 64;
+const MAX_HEIGHT_LEVELS: u8 = 16;
+const MAX_FOREST_GROWTH: usize = 32;
+const MAX_SETTLEMENTS: usize = 64;
+const MAX_BRIDGE_SPAN: usize = 16;
+const MAX_TUNNEL_LENGTH: usize = 32;
+
+/// How the terrain is generated
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GenerationMode {
+  // Noise heights cut into terrain bands
+  Noise = 0,
+  // Wave function collapse over tiles that fit their neighbors
+  Collapse = 1,
+}
+
+impl GenerationMode {
+  pub fn from_u8(value: u8) -> Option<GenerationMode> {
+    match value {
+      0 => Some(GenerationMode::Noise),
+      1 => Some(GenerationMode::Collapse),
+      _ => None,
+    }
+  }
+}
+
+/// Settings that control map generation. Together with the seed and map size
+/// these fully determine the generated world.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenerationConfig {
+  pub mode: GenerationMode,
+  // Overall shape of the land, only for noise terrain
+  pub shape: Shape,
+  // Size of terrain features in cells
+  pub world_scale: f64,
+  // Noise the terrain is made from, and how its octaves are combined
+  pub noise_basis: NoiseBasis,
+  pub fractal: Fractal,
+  // Layers of ever finer noise
+  pub octaves: usize,
+  // How much finer each octave is than the one before
+  pub lacunarity: f64,
+  // How much weaker each octave is than the one before, above 0.0 and at
+  // most 1.0
+  pub persistence: f64,
+  // Most cells domain warping moves the terrain by, 0.0 disables warping
+  pub warp_strength: f64,
+  // Rounds of hydraulic and thermal erosion
+  pub erosion_iterations: usize,
+  // How much material erosion moves, 0.0 disables erosion
+  pub erosion_strength: f32,
+  // Number of height levels, heights go from 1 to `height_levels`
+  pub height_levels: u8,
+  // Terrain for each range of elevation, in ascending order
+  pub terrain_bands: Vec<TerrainBand>,
+  // Deepest closed basin, in levels, that is filled with a lake. 0 disables
+  // lakes
+  pub lake_depth: u8,
+  // Minimum distance in cells between rock landmarks
+  pub landmark_spacing: f64,
+  // Minimum distance in cells between grass patches
+  pub patch_spacing: f64,
+  // Minimum distance in cells between the seeds forests grow from
+  pub forest_spacing: f64,
+  // Rounds of growth spreading forests out from their seeds
+  pub forest_growth: usize,
+  // Highest trees can grow, as a fraction of the height levels
+  pub tree_line: f64,
+  // Lowest snow lies, as a fraction of the height levels. Above 1.0 disables
+  // snow.
+  pub snow_line: f64,
+  // How much of the marsh and shallow shore is covered in reeds and water
+  // lilies, from 0.0 to 1.0
+  pub wetland_density: f64,
+  // Top share of the deposit noise's range that holds ore, clay and fish in
+  // rock, marsh and water, from 0.0 for none to 1.0 for all of it. The noise
+  // is rarely near its extremes, so this isn't the share of cells covered:
+  // about half are at 0.5, but under a tenth at 0.2 and over nine tenths at
+  // 0.8.
+  pub resource_density: f64,
+  // Most settlements to place, 0 disables them
+  pub settlements: usize,
+  // Minimum distance in cells between settlements
+  pub settlement_spacing: f64,
+  // Minimum distance in cells between the centers regions grow from
+  pub region_spacing: f64,
+  // Most cells of water a bridge can cross, 0 disables bridges
+  pub bridge_span: usize,
+  // Most cells of rock a tunnel can bore through, 0 disables tunnels
+  pub tunnel_length: usize,
+}
+
+impl Default for GenerationConfig {
+  fn default() -> GenerationConfig {
+    GenerationConfig {
+      mode: GenerationMode::Noise,
+      shape: Shape::Open,
+      world_scale: 32.0,
+      noise_basis: NoiseBasis::SuperSimplex,
+      fractal: Fractal::Fbm,
+      octaves: 3,
+      lacunarity: 2.0,
+      persistence: 0.5,
+      warp_strength: 0.0,
+      erosion_iterations: 2,
+      erosion_strength: 0.5,
+      height_levels: 4,
+      terrain_bands: vec![
+        TerrainBand {
+          max: 0.125,
+          tile: Tile::WaterDeep,
+        },
+        TerrainBand {
+          max: 0.25,
+          tile: Tile::Water,
+        },
+        TerrainBand {
+          max: 0.375,
+          tile: Tile::Marsh,
+        },
+        TerrainBand {
+          max: 0.75,
+          tile: Tile::Base,
+        },
+        TerrainBand {
+          max: 1.0,
+          tile: Tile::Rock,
+        },
+      ],
+      lake_depth: 2,
+      landmark_spacing: 40.0,
+      patch_spacing: 20.0,
+      forest_spacing: 10.0,
+      forest_growth: 3,
+      tree_line: 0.75,
+      snow_line: 1.0,
+      wetland_density: 0.4,
+      resource_density: 0.4,
+      settlements: 3,
+      settlement_spacing: 32.0,
+      region_spacing: 24.0,
+      bridge_span: 4,
+      tunnel_length: 8,
+    }
+  }
+}
+
+impl GenerationConfig {
+  /// Encode the settings that differ from the defaults as `key:value` pairs
+  pub fn encode(&self) -> Vec<String> {
+    let default = GenerationConfig::default();
+    let mut pairs = Vec::new();
+    if self.mode != default.mode {
+      pairs.push(format!("gm:{}", self.mode as u8));
+    }
+    if self.shape != default.shape {
+      pairs.push(format!("sh:{}", self.shape as u8));
+    }
+    if self.world_scale != default.world_scale {
+      pairs.push(format!("ws:{}", self.world_scale));
+    }
+    if self.noise_basis != default.noise_basis {
+      pairs.push(format!("nb:{}", self.noise_basis as u8));
+    }
+    if self.fractal != default.fractal {
+      pairs.push(format!("fr:{}", self.fractal as u8));
+    }
+    if self.octaves != default.octaves {
+      pairs.push(format!("oc:{}", self.octaves));
+    }
+    if self.lacunarity != default.lacunarity {
+      pairs.push(format!("la:{}", self.lacunarity));
+    }
+    if self.persistence != default.persistence {
+      pairs.push(format!("pe:{}", self.persistence));
+    }
+    if self.warp_strength != default.warp_strength {
+      pairs.push(format!("dw:{}", self.warp_strength));
+    }
+    if self.erosion_iterations != default.erosion_iterations {
+      pairs.push(format!("ei:{}", self.erosion_iterations));
+    }
+    if self.erosion_strength != default.erosion_strength {
+      pairs.push(format!("es:{}", self.erosion_strength));
+    }
+    if self.height_levels != default.height_levels {
+      pairs.push(format!("hl:{}", self.height_levels));
+    }
+    if self.terrain_bands != default.terrain_bands {
+      let bands = self
+        .terrain_bands
+        .iter()
+        .filter_map(|band| {
+          let &(name, _) = BAND_TILES.iter().find(|&&(_, tile)| tile == band.tile)?;
+          Some(format!("{}{}", name, band.max))
+        })
+        .collect::<Vec<String>>();
+      pairs.push(format!("tb:{}", bands.join(",")));
+    }
+    if self.lake_depth != default.lake_depth {
+      pairs.push(format!("ld:{}", self.lake_depth));
+    }
+    if self.landmark_spacing != default.landmark_spacing {
+      pairs.push(format!("ls:{}", self.landmark_spacing));
+    }
+    if self.patch_spacing != default.patch_spacing {
+      pairs.push(format!("ps:{}", self.patch_spacing));
+    }
+    if self.forest_spacing != default.forest_spacing {
+      pairs.push(format!("fs:{}", self.forest_spacing));
+    }
+    if self.forest_growth != default.forest_growth {
+      pairs.push(format!("fg:{}", self.forest_growth));
+    }
+    if self.tree_line != default.tree_line {
+      pairs.push(format!("tl:{}", self.tree_line));
+    }
+    if self.snow_line != default.snow_line {
+      pairs.push(format!("sl:{}", self.snow_line));
+    }
+    if self.wetland_density != default.wetland_density {
+      pairs.push(format!("wd:{}", self.wetland_density));
+    }
+    if self.resource_density != default.resource_density {
+      pairs.push(format!("rd:{}", self.resource_density));
+    }
+    if self.settlements != default.settlements {
+      pairs.push(format!("st:{}", self.settlements));
+    }
+    if self.settlement_spacing != default.settlement_spacing {
+      pairs.push(format!("ss:{}", self.settlement_spacing));
+    }
+    if self.region_spacing != default.region_spacing {
+      pairs.push(format!("rs:{}", self.region_spacing));
+    }
+    if self.bridge_span != default.bridge_span {
+      pairs.push(format!("bs:{}", self.bridge_span));
+    }
+    if self.tunnel_length != default.tunnel_length {
+      pairs.push(format!("tu:{}", self.tunnel_length));
+    }
+    pairs
+  }
+
+  /// Set a single setting from an encoded `key` and `value`
+  pub fn decode(&mut self, key: &str, value: &str) -> Result<(), String> {
+    let number = |max: usize| {
+      value
+        .parse::<usize>()
+        .ok()
+        .filter(|&number| number <= max)
+        .ok_or(format!("Invalid value for {}", key))
+    };
+    let distance = || {
+      value
+        .parse::<f64>()
+        .ok()
+        .filter(|&distance| distance >= 1.0 && distance.is_finite())
+        .ok_or(format!("Invalid value for {}", key))
+    };
+    let positive = || {
+      value
+        .parse::<f64>()
+        .ok()
+        .filter(|&value| value > 0.0 && value.is_finite())
+        .ok_or(format!("Invalid value for {}", key))
+    };
+    match key {
+      "gm" => {
+        self.mode = value
+          .parse::<u8>()
+          .ok()
+          .and_then(GenerationMode::from_u8)
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      "sh" => {
+        self.shape = value
+          .parse::<u8>()
+          .ok()
+          .and_then(Shape::from_u8)
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      "ws" => self.world_scale = positive()?,
+      "nb" => {
+        self.noise_basis = value
+          .parse::<u8>()
+          .ok()
+          .and_then(NoiseBasis::from_u8)
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      "fr" => {
+        self.fractal = value
+          .parse::<u8>()
+          .ok()
+          .and_then(Fractal::from_u8)
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      "oc" => {
+        self.octaves = number(MAX_OCTAVES)
+          .ok()
+          .filter(|&octaves| octaves > 0)
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      "la" => self.lacunarity = positive()?,
+      "pe" => {
+        self.persistence = value
+          .parse::<f64>()
+          .ok()
+          .filter(|&persistence| persistence > 0.0 && persistence <= 1.0)
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      "dw" => {
+        self.warp_strength = value
+          .parse::<f64>()
+          .ok()
+          .filter(|&strength| strength >= 0.0 && strength.is_finite())
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      "ei" => self.erosion_iterations = number(MAX_EROSION_ITERATIONS)?,
+      "es" => {
+        self.erosion_strength = value
+          .parse::<f32>()
+          .ok()
+          .filter(|&strength| strength >= 0.0 && strength.is_finite())
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      "hl" => {
+        self.height_levels = value
+          .parse::<u8>()
+          .ok()
+          .filter(|&levels| levels > 0 && levels <= MAX_HEIGHT_LEVELS)
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      "tb" => {
+        self.terrain_bands = value
+          .split(',')
+          .map(|band| {
+            let mut chars = band.chars();
+            let name = chars.next()?;
+            let &(_, tile) = BAND_TILES.iter().find(|&&(n, _)| n == name)?;
+            let max = chars.as_str().parse::<f64>().ok().filter(|max| max.is_finite())?;
+            Some(TerrainBand { max, tile })
+          })
+          .collect::<Option<Vec<TerrainBand>>>()
+          // Bands go up in order, from deep water to rock, and the last
+          // reaches the top of the noise
+          .filter(|bands| {
+            let order = |tile: Tile| BAND_TILES.iter().position(|&(_, t)| t == tile);
+            bands
+              .windows(2)
+              .all(|pair| pair[0].max < pair[1].max && order(pair[0].tile) <= order(pair[1].tile))
+              && bands.last().is_some_and(|band| band.max >= 1.0)
+          })
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      "ld" => {
+        self.lake_depth = value
+          .parse::<u8>()
+          .map_err(|_| format!("Invalid value for {}", key))?
+      }
+      "ls" => self.landmark_spacing = distance()?,
+      "ps" => self.patch_spacing = distance()?,
+      "fs" => self.forest_spacing = distance()?,
+      // Tree spacing from before forests were grown in clusters. Codes that
+      // set it still load, but the value is ignored.
+      "ts" => {
+        distance()?;
+      }
+      "fg" => self.forest_growth = number(MAX_FOREST_GROWTH)?,
+      "st" => self.settlements = number(MAX_SETTLEMENTS)?,
+      "ss" => self.settlement_spacing = distance()?,
+      "rs" => self.region_spacing = distance()?,
+      "bs" => self.bridge_span = number(MAX_BRIDGE_SPAN)?,
+      "tu" => self.tunnel_length = number(MAX_TUNNEL_LENGTH)?,
+      "tl" => {
+        self.tree_line = value
+          .parse::<f64>()
+          .ok()
+          .filter(|line| (0.0..=1.0).contains(line))
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      "sl" => {
+        self.snow_line = value
+          .parse::<f64>()
+          .ok()
+          .filter(|&line| line >= 0.0 && line.is_finite())
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      "wd" => {
+        self.wetland_density = value
+          .parse::<f64>()
+          .ok()
+          .filter(|density| (0.0..=1.0).contains(density))
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      "rd" => {
+        self.resource_density = value
+          .parse::<f64>()
+          .ok()
+          .filter(|density| (0.0..=1.0).contains(density))
+          .ok_or(format!("Invalid value for {}", key))?
+      }
+      _ => return Err(format!("Unknown setting {}", key)),
+    }
+    Ok(())
+  }
+}
+
+impl GenerationConfig {
+  // Elevations are rounded to steps of half a level, so every level spans two
+  // steps
+  fn steps(&self) -> u32 {
+    self.height_levels as u32 * 2
+  }
+
+  fn band_tile(&self, elevation: f64) -> Tile {
+    self
+      .terrain_bands
+      .iter()
+      .find(|band| elevation < band.max)
+      .or_else(|| self.terrain_bands.last())
+      .map_or(Tile::Base, |band| band.tile)
+  }
+
+  /// Height level and terrain tile for an elevation between 0.0 and 1.0
+  pub fn quantize(&self, elevation: f64) -> (u8, Tile) {
+    let step = (elevation.clamp(0.0, 1.0) * self.steps() as f64).round() as u32;
+    let height = (step / 2 + 1).min(self.height_levels as u32) as u8;
+    (height, self.band_tile(step as f64 / self.steps() as f64))
+  }
+
+  /// Highest height level trees grow at
+  pub fn tree_line_height(&self) -> u8 {
+    (self.height_levels as f64 * self.tree_line).floor() as u8
+  }
+
+  /// Lowest height level snow lies at, above the highest level when snow is
+  /// disabled
+  pub fn snow_line_height(&self) -> u8 {
+    (self.height_levels as f64 * self.snow_line).ceil().min(u8::MAX as f64) as u8
+  }
+
+  /// Lowest elevation that isn't water
+  pub fn land_min(&self) -> f64 {
+    self
+      .terrain_bands
+      .iter()
+      .filter(|band| matches!(band.tile, Tile::Water | Tile::WaterDeep))
+      .map(|band| band.max)
+      .fold(0.0, f64::max)
+  }
+
+  /// Terrain tile at the bottom of a height level
+  pub fn level_tile(&self, height: u8) -> Tile {
+    let step = (height.max(1) as u32 - 1) * 2;
+    self.band_tile(step as f64 / self.steps() as f64)
+  }
+
+  /// Every height level a terrain tile can be generated at
+  pub fn tile_heights(&self, tile: Tile) -> Vec<u8> {
+    let mut heights = (0..=self.steps())
+      .map(|step| self.quantize(step as f64 / self.steps() as f64))
+      .filter(|&(_, t)| t == tile)
+      .map(|(height, _)| height)
+      .collect::<Vec<u8>>();
+    heights.dedup();
+    heights
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::GenerationConfig;
+  use crate::atlas::Tile;
+  use crate::sampler::{Fractal, NoiseBasis};
+
+  #[test]
+  fn quantize_default_levels() {
+    let config = GenerationConfig::default();
+    assert_eq!(config.quantize(0.0), (1, Tile::WaterDeep));
+    assert_eq!(config.quantize(0.15), (1, Tile::Water));
+    assert_eq!(config.quantize(0.3), (2, Tile::Marsh));
+    assert_eq!(config.quantize(0.5), (3, Tile::Base));
+    assert_eq!(config.quantize(1.0), (4, Tile::Rock));
+    assert_eq!(config.tile_heights(Tile::Water), vec![1]);
+    assert_eq!(config.tile_heights(Tile::Base), vec![2, 3]);
+    assert_eq!(config.tile_heights(Tile::Rock), vec![4]);
+    assert_eq!(config.level_tile(1), Tile::WaterDeep);
+    assert_eq!(config.level_tile(2), Tile::Marsh);
+  }
+
+  #[test]
+  fn quantize_more_levels() {
+    let config = GenerationConfig {
+      height_levels: 10,
+      ..GenerationConfig::default()
+    };
+    assert_eq!(config.quantize(0.0).0, 1);
+    assert_eq!(config.quantize(0.55).0, 6);
+    assert_eq!(config.quantize(1.0).0, 10);
+    assert_eq!(config.tile_heights(Tile::Rock), vec![8, 9, 10]);
+  }
+
+  #[test]
+  fn encode_levels_and_bands() {
+    let mut config = GenerationConfig {
+      height_levels: 8,
+      ..GenerationConfig::default()
+    };
+    config.terrain_bands[1].max = 0.3;
+    let pairs = config.encode();
+    assert_eq!(pairs, vec!["hl:8", "tb:d0.125,w0.3,m0.375,b0.75,r1"]);
+    let mut decoded = GenerationConfig::default();
+    for pair in pairs {
+      let (key, value) = pair.split_once(':').unwrap();
+      decoded.decode(key, value).unwrap();
+    }
+    assert_eq!(decoded, config);
+  }
+
+  #[test]
+  fn encode_noise() {
+    let config = GenerationConfig {
+      noise_basis: NoiseBasis::Worley,
+      fractal: Fractal::Ridged,
+      octaves: 6,
+      lacunarity: 2.5,
+      warp_strength: 12.0,
+      ..GenerationConfig::default()
+    };
+    let pairs = config.encode();
+    assert_eq!(pairs, vec!["nb:3", "fr:1", "oc:6", "la:2.5", "dw:12"]);
+    let mut decoded = GenerationConfig::default();
+    for pair in pairs {
+      let (key, value) = pair.split_once(':').unwrap();
+      decoded.decode(key, value).unwrap();
+    }
+    assert_eq!(decoded, config);
+    assert!(decoded.decode("oc", "0").is_err());
+    assert!(decoded.decode("oc", "8").is_ok());
+    assert!(decoded.decode("oc", "9").is_err());
+    assert!(decoded.decode("pe", "1").is_ok());
+    assert!(decoded.decode("pe", "0").is_err());
+    assert!(decoded.decode("pe", "1.5").is_err());
+    assert!(decoded.decode("nb", "9").is_err());
+  }
+
+  #[test]
+  fn decode_rejects_huge_values() {
+    let mut config = GenerationConfig::default();
+    for key in ["ei", "hl", "fg", "st", "bs", "tu"] {
+      assert!(config.decode(key, "1000000").is_err(), "{}", key);
+      assert!(config.decode(key, "4").is_ok(), "{}", key);
+    }
+    assert!(config.decode("ei", "64").is_ok());
+    assert!(config.decode("ei", "65").is_err());
+    assert!(config.decode("hl", "16").is_ok());
+    assert!(config.decode("hl", "17").is_err());
+  }
+
+  #[test]
+  fn decode_rejects_bad_bands() {
+    let mut config = GenerationConfig::default();
+    for value in ["bNaN", "binf", "r0.9,b1", "b0.5", "w0.5,b0.5,r1", "x1"] {
+      assert!(config.decode("tb", value).is_err(), "{}", value);
+    }
+    assert!(config.decode("tb", "w0.5,b1").is_ok());
+    assert!(config.decode("tb", "b1.5").is_ok());
+  }
+
+  #[test]
+  fn decode_accepts_tree_spacing() {
+    let mut config = GenerationConfig::default();
+    assert!(config.decode("ts", "1.5").is_ok());
+    assert!(config.decode("ts", "-1").is_err());
+    assert_eq!(config.encode(), GenerationConfig::default().encode());
+  }
+}
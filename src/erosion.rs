@@ -0,0 +1,225 @@
+use rand::rngs::StdRng;
+use rand::Rng;
+
+// Hydraulic erosion
+const INERTIA: f32 = 0.05;
+const CAPACITY: f32 = 2.0;
+const MIN_CAPACITY: f32 = 0.0005;
+const ERODE_SPEED: f32 = 0.3;
+const DEPOSIT_SPEED: f32 = 0.3;
+const EVAPORATE_SPEED: f32 = 0.02;
+const GRAVITY: f32 = 4.0;
+const MAX_LIFETIME: usize = 30;
+// Thermal erosion
+// Steepest difference in height between neighbors before material slides
+const TALUS: f32 = 0.04;
+
+/// Continuous terrain heights between 0.0 and 1.0, eroded before being
+/// quantized into the map's height levels.
+pub struct Heightfield {
+  pub row_count: usize,
+  pub col_count: usize,
+  pub values: Vec<f32>,
+}
+
+impl Heightfield {
+  pub fn new(row_count: usize, col_count: usize) -> Heightfield {
+    Heightfield {
+      row_count,
+      col_count,
+      values: vec![0.0; row_count * col_count],
+    }
+  }
+
+  /// Run both kinds of erosion `iterations` times. `strength` scales how much
+  /// material is moved, 0.0 leaves the terrain untouched.
+  pub fn erode(&mut self, iterations: usize, strength: f32, rng: &mut StdRng) {
+    if strength <= 0.0 || self.row_count < 2 || self.col_count < 2 {
+      return;
+    }
+    for _ in 0..iterations {
+      // One droplet per cell
+      for _ in 0..(self.row_count * self.col_count) {
+        let x = rng.gen_range(0.0..(self.col_count - 1) as f32);
+        let y = rng.gen_range(0.0..(self.row_count - 1) as f32);
+        self.hydraulic(x, y, strength);
+      }
+      self.thermal(strength);
+    }
+    for value in self.values.iter_mut() {
+      *value = value.clamp(0.0, 1.0);
+    }
+  }
+
+  // Bilinear height and gradient at a point
+  fn height_and_gradient(&self, x: f32, y: f32) -> (f32, f32, f32) {
+    let col = x as usize;
+    let row = y as usize;
+    let u = x - col as f32;
+    let v = y - row as f32;
+    let i = row * self.col_count + col;
+    let nw = self.values[i];
+    let ne = self.values[i + 1];
+    let sw = self.values[i + self.col_count];
+    let se = self.values[i + self.col_count + 1];
+    let gradient_x = (ne - nw) * (1.0 - v) + (se - sw) * v;
+    let gradient_y = (sw - nw) * (1.0 - u) + (se - ne) * u;
+    let height = nw * (1.0 - u) * (1.0 - v) + ne * u * (1.0 - v) + sw * (1.0 - u) * v + se * u * v;
+    (height, gradient_x, gradient_y)
+  }
+
+  // Spread a change in height over the four cells around a point
+  fn add(&mut self, x: f32, y: f32, amount: f32) {
+    let col = x as usize;
+    let row = y as usize;
+    let u = x - col as f32;
+    let v = y - row as f32;
+    let i = row * self.col_count + col;
+    self.values[i] += amount * (1.0 - u) * (1.0 - v);
+    self.values[i + 1] += amount * u * (1.0 - v);
+    self.values[i + self.col_count] += amount * (1.0 - u) * v;
+    self.values[i + self.col_count + 1] += amount * u * v;
+  }
+
+  // Simulate a droplet of water running downhill, picking up sediment where it
+  // speeds up and dropping it where it slows down
+  fn hydraulic(&mut self, mut x: f32, mut y: f32, strength: f32) {
+    let max_x = (self.col_count - 1) as f32;
+    let max_y = (self.row_count - 1) as f32;
+    let (mut dir_x, mut dir_y) = (0.0, 0.0);
+    let mut speed = 1.0;
+    let mut water = 1.0;
+    let mut sediment = 0.0;
+
+    for _ in 0..MAX_LIFETIME {
+      let (height, gradient_x, gradient_y) = self.height_and_gradient(x, y);
+      dir_x = dir_x * INERTIA - gradient_x * (1.0 - INERTIA);
+      dir_y = dir_y * INERTIA - gradient_y * (1.0 - INERTIA);
+      let length = (dir_x * dir_x + dir_y * dir_y).sqrt();
+      if length < f32::EPSILON {
+        break;
+      }
+      dir_x /= length;
+      dir_y /= length;
+
+      let (next_x, next_y) = (x + dir_x, y + dir_y);
+      if next_x < 0.0 || next_x >= max_x || next_y < 0.0 || next_y >= max_y {
+        break;
+      }
+
+      let delta = self.height_and_gradient(next_x, next_y).0 - height;
+      let capacity = (-delta * speed * water * CAPACITY).max(MIN_CAPACITY);
+      if sediment > capacity || delta > 0.0 {
+        // Fill the pit when going uphill, otherwise drop the excess sediment
+        let deposit = if delta > 0.0 {
+          delta.min(sediment)
+        } else {
+          (sediment - capacity) * DEPOSIT_SPEED * strength
+        };
+        sediment -= deposit;
+        self.add(x, y, deposit);
+      } else {
+        // Never erode more than the difference in height, to avoid digging pits
+        let erode = ((capacity - sediment) * ERODE_SPEED * strength).min(-delta);
+        sediment += erode;
+        self.add(x, y, -erode);
+      }
+
+      speed = (speed * speed - delta * GRAVITY).max(0.0).sqrt();
+      water *= 1.0 - EVAPORATE_SPEED;
+      x = next_x;
+      y = next_y;
+    }
+
+    // Drop whatever is left where the droplet stops, so erosion moves material
+    // rather than removing it
+    self.add(x, y, sediment);
+  }
+
+  // Let material slide down slopes that are steeper than the talus angle
+  fn thermal(&mut self, strength: f32) {
+    let mut deltas = vec![0.0; self.values.len()];
+    let rate = 0.5 * strength.min(1.0);
+    for row in 0..self.row_count {
+      for col in 0..self.col_count {
+        let i = row * self.col_count + col;
+        // Compare with the east and south neighbors, so each pair is only
+        // visited once
+        let mut neighbors = Vec::with_capacity(2);
+        if col + 1 < self.col_count {
+          neighbors.push(i + 1);
+        }
+        if row + 1 < self.row_count {
+          neighbors.push(i + self.col_count);
+        }
+        for n in neighbors {
+          let difference = self.values[i] - self.values[n];
+          if difference.abs() > TALUS {
+            let amount = (difference.abs() - TALUS) * 0.5 * rate * difference.signum();
+            deltas[i] -= amount;
+            deltas[n] += amount;
+          }
+        }
+      }
+    }
+    for (value, delta) in self.values.iter_mut().zip(deltas) {
+      *value += delta;
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Heightfield;
+  use rand::rngs::StdRng;
+  use rand::SeedableRng;
+
+  // A cone, highest in the middle
+  fn cone(size: usize) -> Heightfield {
+    let mut heightfield = Heightfield::new(size, size);
+    let center = size as f32 / 2.0;
+    for row in 0..size {
+      for col in 0..size {
+        let distance = ((row as f32 - center).powi(2) + (col as f32 - center).powi(2)).sqrt();
+        heightfield.values[row * size + col] = (1.0 - distance / center).max(0.0);
+      }
+    }
+    heightfield
+  }
+
+  #[test]
+  fn erosion_is_deterministic() {
+    let mut a = cone(16);
+    let mut b = cone(16);
+    a.erode(2, 0.5, &mut StdRng::seed_from_u64(1));
+    b.erode(2, 0.5, &mut StdRng::seed_from_u64(1));
+    assert_eq!(a.values, b.values);
+  }
+
+  #[test]
+  fn erosion_changes_terrain() {
+    let original = cone(16);
+    let mut eroded = cone(16);
+    eroded.erode(2, 0.5, &mut StdRng::seed_from_u64(1));
+    assert_ne!(original.values, eroded.values);
+    assert!(eroded.values.iter().all(|&v| (0.0..=1.0).contains(&v)));
+  }
+
+  #[test]
+  fn thermal_erosion_flattens_steep_slopes() {
+    let mut heightfield = Heightfield::new(1, 2);
+    heightfield.values = vec![1.0, 0.0];
+    heightfield.thermal(1.0);
+    assert!(heightfield.values[0] < 1.0);
+    assert!(heightfield.values[1] > 0.0);
+    assert_eq!(heightfield.values[0] + heightfield.values[1], 1.0);
+  }
+
+  #[test]
+  fn zero_strength_leaves_terrain_untouched() {
+    let original = cone(16);
+    let mut eroded = cone(16);
+    eroded.erode(2, 0.0, &mut StdRng::seed_from_u64(1));
+    assert_eq!(original.values, eroded.values);
+  }
+}
@@ -0,0 +1,193 @@
+use crate::atlas::Tile;
+use crate::map::Map;
+use crate::poisson::poisson_disk;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Trees planted around each forest seed
+const CLUMP_RADIUS: isize = 3;
+const CLUMP_DENSITY: f64 = 0.6;
+// Chance of a tree taking root next to only a couple of others
+const SPREAD_CHANCE: f64 = 0.3;
+// Chance of keeping a tree one and two cells away from a road or water
+const THINNING: [f64; 2] = [0.25, 0.6];
+
+impl Map {
+  // Flat ground below the tree line with nothing on it yet
+  fn can_grow_tree(&self, row: usize, col: usize, tree_line: u8) -> bool {
+    self.get_tile(row, col) == Tile::Base
+      && self.get_tile_2(row, col) == Tile::Empty
+      && self.get_height(row, col) <= tree_line
+  }
+
+  // Distance to the nearest road or water, if it is close enough to thin
+  // the trees
+  fn clearing_distance(&self, row: usize, col: usize) -> Option<usize> {
+    let reach = THINNING.len() as isize;
+    let mut nearest = None;
+    for d_row in -reach..=reach {
+      for d_col in -reach..=reach {
+        let (n_row, n_col) = (row as isize + d_row, col as isize + d_col);
+        if n_row < 0 || n_col < 0 || n_row >= self.row_count as isize || n_col >= self.col_count as isize {
+          continue;
+        }
+        let tile = self.get_tile(n_row as usize, n_col as usize);
+        if tile.is_road() || matches!(tile, Tile::Water | Tile::WaterDeep) {
+          let distance = d_row.abs().max(d_col.abs()) as usize;
+          nearest = Some(nearest.map_or(distance, |n: usize| n.min(distance)));
+        }
+      }
+    }
+    nearest
+  }
+
+  // Grow forests in clumps: plant trees around evenly spaced seeds, then let
+  // them spread like a cellular automaton. Trees are thinned out next to roads
+  // and water, and turn to conifers towards the tree line.
+  pub(crate) fn grow_forests(&mut self, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let tree_line = self.config.tree_line_height();
+    let col_count = self.col_count;
+    let size = self.row_count * col_count;
+    let can_grow = (0..size)
+      .map(|i| self.can_grow_tree(i / col_count, i % col_count, tree_line))
+      .collect::<Vec<bool>>();
+
+    let mut trees = vec![false; size];
+    let seeds = poisson_disk(
+      self.row_count,
+      col_count,
+      self.config.forest_spacing,
+      usize::MAX,
+      &mut rng,
+      |row, col| can_grow[row * col_count + col],
+    );
+    for (row, col) in seeds {
+      for d_row in -CLUMP_RADIUS..=CLUMP_RADIUS {
+        for d_col in -CLUMP_RADIUS..=CLUMP_RADIUS {
+          let (n_row, n_col) = (row as isize + d_row, col as isize + d_col);
+          if d_row * d_row + d_col * d_col > CLUMP_RADIUS * CLUMP_RADIUS
+            || n_row < 0
+            || n_col < 0
+            || n_row >= self.row_count as isize
+            || n_col >= col_count as isize
+          {
+            continue;
+          }
+          let i = n_row as usize * col_count + n_col as usize;
+          if can_grow[i] && rng.gen_bool(CLUMP_DENSITY) {
+            trees[i] = true;
+          }
+        }
+      }
+    }
+
+    // Lone trees die out, gaps inside stands fill in and the edges creep
+    // outwards
+    for _ in 0..self.config.forest_growth {
+      trees = (0..size)
+        .map(|i| {
+          if !can_grow[i] {
+            return false;
+          }
+          let count = self
+            .neighbors_8(i / col_count, i % col_count)
+            .iter()
+            .filter(|n| trees[n.0 * col_count + n.1])
+            .count();
+          if trees[i] {
+            count >= 3
+          } else {
+            count >= 4 || (count >= 2 && rng.gen_bool(SPREAD_CHANCE))
+          }
+        })
+        .collect();
+    }
+
+    for i in (0..size).filter(|&i| trees[i]) {
+      let (row, col) = (i / col_count, i % col_count);
+      if let Some(distance) = self.clearing_distance(row, col) {
+        if distance == 0 || !rng.gen_bool(THINNING[distance - 1]) {
+          continue;
+        }
+      }
+      // More conifers higher up
+      let height = self.get_height(row, col) as f64;
+      let conifers = ((height - 1.0) / tree_line.max(1) as f64).clamp(0.0, 1.0);
+      let tree = if rng.gen_bool(conifers) {
+        Tile::TreeAlt
+      } else {
+        Tile::Tree
+      };
+      self.set_tile_2(row, col, tree);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::atlas::Tile;
+  use crate::map::Map;
+
+  fn is_tree(tile: Tile) -> bool {
+    tile == Tile::Tree || tile == Tile::TreeAlt
+  }
+
+  #[test]
+  fn forests_grow_in_clumps() {
+    let mut map = Map::new(48, 48);
+    map.height_map = vec![2; 48 * 48];
+    map.grow_forests(1);
+    let trees = (0..48 * 48)
+      .filter(|&i| is_tree(map.tile_map_2[i]))
+      .collect::<Vec<usize>>();
+    assert!(!trees.is_empty());
+    assert!(trees.len() < 48 * 48);
+    // Almost every tree has other trees around it
+    let lonely = trees
+      .iter()
+      .filter(|&&i| {
+        map
+          .neighbors_8(i / 48, i % 48)
+          .iter()
+          .filter(|n| is_tree(map.get_tile_2(n.0, n.1)))
+          .count()
+          < 2
+      })
+      .count();
+    assert!(lonely * 10 < trees.len());
+  }
+
+  #[test]
+  fn no_trees_above_the_tree_line() {
+    let mut map = Map::new(32, 32);
+    // The default tree line is at height 3 of 4
+    map.height_map = (0..32 * 32).map(|i| if i % 32 < 16 { 3 } else { 4 }).collect();
+    map.grow_forests(1);
+    for row in 0..32 {
+      for col in 0..32 {
+        if map.get_height(row, col) > 3 {
+          assert_eq!(map.get_tile_2(row, col), Tile::Empty);
+        }
+      }
+    }
+    assert!(map.tile_map_2.iter().any(|&t| is_tree(t)));
+  }
+
+  #[test]
+  fn forests_thin_out_by_roads() {
+    let mut map = Map::new(48, 48);
+    map.height_map = vec![2; 48 * 48];
+    for row in 0..48 {
+      map.set_tile(row, 24, Tile::Road2);
+    }
+    map.grow_forests(1);
+    let count = |cols: &[usize]| {
+      (0..48)
+        .flat_map(|row| cols.iter().map(move |&col| (row, col)))
+        .filter(|&(row, col)| is_tree(map.get_tile_2(row, col)))
+        .count()
+    };
+    assert!(count(&[23, 25]) * 2 < count(&[8, 40]).max(1));
+  }
+}
@@ -1,6 +1,14 @@
 use crate::atlas::Tile;
+use crate::config::{GenerationConfig, GenerationMode};
 use crate::map::Map;
-use crate::utils::set_panic_hook;
+use crate::minimap::Minimap;
+use crate::pipeline::Pipeline;
+use crate::region::Region;
+use crate::resource::Resource;
+use crate::settlement::Settlement;
+use crate::shape::Shape;
+use crate::utils::{hash_str, set_panic_hook};
+use crate::world_code::WorldCode;
 // use rand::Rng;
 use std::fmt;
 use wasm_bindgen::prelude::*;
@@ -8,17 +16,116 @@ use wasm_bindgen::prelude::*;
 #[wasm_bindgen]
 pub struct Game {
   map: Map,
+  minimap: Option<Minimap>,
+  code: WorldCode,
 }
 
-#[wasm_bindgen]
 impl Game {
-  pub fn new(row_count: usize, col_count: usize, seed: u32) -> Game {
+  fn generate(code: WorldCode) -> Game {
+    Game::generate_with(code, &Pipeline::default())
+  }
+
+  fn generate_with(code: WorldCode, pipeline: &Pipeline) -> Game {
     set_panic_hook();
-    let mut map = Map::new(row_count, col_count);
+    let mut map = Map::new(code.row_count, code.col_count);
     // Debug
     // let s = rand::thread_rng().gen_range(0..u32::max_value());
-    map.generate(seed);
-    Game { map }
+    pipeline.run(&mut map, code.seed, &code.config);
+    Game {
+      map,
+      minimap: None,
+      code,
+    }
+  }
+
+  /// Generate a world with custom passes. The world code doesn't record the
+  /// passes, so it only reproduces the world with the same pipeline.
+  pub fn with_pipeline(
+    row_count: usize,
+    col_count: usize,
+    seed: &str,
+    config: GenerationConfig,
+    pipeline: &Pipeline,
+  ) -> Game {
+    Game::generate_with(
+      WorldCode {
+        seed: hash_str(seed),
+        row_count,
+        col_count,
+        config,
+      },
+      pipeline,
+    )
+  }
+
+  pub fn tile_map(&self) -> &[Tile] {
+    &self.map.tile_map
+  }
+
+  pub fn tile_map_2(&self) -> &[Tile] {
+    &self.map.tile_map_2
+  }
+
+  pub fn height_map(&self) -> &[u8] {
+    &self.map.height_map
+  }
+
+  pub fn lake_map(&self) -> &[u16] {
+    &self.map.lake_map
+  }
+
+  pub fn region_map(&self) -> &[u16] {
+    &self.map.region_map
+  }
+
+  pub fn regions(&self) -> &[Region] {
+    self.map.regions()
+  }
+
+  pub fn resource_map(&self) -> &[Resource] {
+    &self.map.resource_map
+  }
+
+  pub fn resource_amounts(&self) -> &[u8] {
+    &self.map.resource_amounts
+  }
+
+  pub fn settlements(&self) -> &[Settlement] {
+    self.map.settlements()
+  }
+}
+
+#[wasm_bindgen]
+impl Game {
+  /// Generate a world from any seed string. Collapse terrain has no shape,
+  /// so `shape` only applies to noise terrain.
+  pub fn new(row_count: usize, col_count: usize, seed: &str, shape: Shape, mode: GenerationMode) -> Game {
+    let shape = match mode {
+      GenerationMode::Noise => shape,
+      GenerationMode::Collapse => Shape::Open,
+    };
+    Game::generate(WorldCode {
+      seed: hash_str(seed),
+      row_count,
+      col_count,
+      config: GenerationConfig {
+        mode,
+        shape,
+        ..Default::default()
+      },
+    })
+  }
+  /// Generate the world described by a world code
+  #[wasm_bindgen(js_name = fromCode)]
+  pub fn from_code(code: &str) -> Result<Game, JsValue> {
+    WorldCode::parse(code)
+      .map(Game::generate)
+      .map_err(|e| JsValue::from_str(&e))
+  }
+  /// Shareable code that reproduces this world
+  #[wasm_bindgen(js_name = worldCode)]
+  pub fn js_world_code(&self) -> String {
+    self.code.to_string()
   }
   #[wasm_bindgen(js_name = tileMapPtr)]
   pub fn js_tile_map_ptr(&self) -> *const Tile {
@@ -32,6 +139,100 @@ impl Game {
   pub fn js_height_map_ptr(&self) -> *const u8 {
     self.map.height_map_ptr()
   }
+  #[wasm_bindgen(js_name = lakeMapPtr)]
+  pub fn js_lake_map_ptr(&self) -> *const u16 {
+    self.map.lake_map_ptr()
+  }
+  /// Water level of each lake, indexed by lake id - 1
+  #[wasm_bindgen(js_name = lakeLevels)]
+  pub fn js_lake_levels(&self) -> Vec<u8> {
+    self.map.lakes.iter().map(|lake| lake.level).collect()
+  }
+  #[wasm_bindgen(js_name = regionMapPtr)]
+  pub fn js_region_map_ptr(&self) -> *const u16 {
+    self.map.region_map_ptr()
+  }
+  /// Main terrain tile of each region, indexed by region id - 1
+  #[wasm_bindgen(js_name = regionTerrain)]
+  pub fn js_region_terrain(&self) -> Vec<u8> {
+    self.map.regions().iter().map(|region| region.terrain as u8).collect()
+  }
+  /// Area in cells of each region, indexed by region id - 1
+  #[wasm_bindgen(js_name = regionAreas)]
+  pub fn js_region_areas(&self) -> Vec<u32> {
+    self.map.regions().iter().map(|region| region.area as u32).collect()
+  }
+  /// Every pair of neighboring regions once, as two region ids per border
+  #[wasm_bindgen(js_name = regionBorders)]
+  pub fn js_region_borders(&self) -> Vec<u16> {
+    self
+      .map
+      .regions()
+      .iter()
+      .flat_map(|region| {
+        region
+          .neighbors
+          .iter()
+          .filter(move |&&id| id > region.id)
+          .flat_map(move |&id| vec![region.id, id])
+      })
+      .collect()
+  }
+  #[wasm_bindgen(js_name = resourceMapPtr)]
+  pub fn js_resource_map_ptr(&self) -> *const Resource {
+    self.map.resource_map_ptr()
+  }
+  /// How much of its resource each cell holds, from 0 to 100
+  #[wasm_bindgen(js_name = resourceAmountsPtr)]
+  pub fn js_resource_amounts_ptr(&self) -> *const u8 {
+    self.map.resource_amounts_ptr()
+  }
+  /// Box around each settlement as top, left, bottom and right cells, four
+  /// numbers per settlement
+  #[wasm_bindgen(js_name = settlementBounds)]
+  pub fn js_settlement_bounds(&self) -> Vec<u32> {
+    self
+      .map
+      .settlements()
+      .iter()
+      .flat_map(|settlement| {
+        let (top, left, bottom, right) = settlement.bounds;
+        vec![top as u32, left as u32, bottom as u32, right as u32]
+      })
+      .collect()
+  }
+  /// Describe every inconsistency in the map, e.g. before saving it
+  #[wasm_bindgen(js_name = validate)]
+  pub fn js_validate(&self) -> Vec<String> {
+    self
+      .map
+      .validate()
+      .iter()
+      .map(|violation| violation.to_string())
+      .collect()
+  }
+  /// RGBA overview of the map scaled to `width` x `height` pixels, rendered
+  /// on first use and kept up to date by `updateMinimap`. Read
+  /// `width * height * 4` bytes from the pointer.
+  #[wasm_bindgen(js_name = minimapPtr)]
+  pub fn js_minimap_ptr(&mut self, width: usize, height: usize) -> *const u8 {
+    match &self.minimap {
+      Some(minimap) if minimap.width == width && minimap.height == height => {}
+      _ => {
+        let mut minimap = Minimap::new(width, height);
+        minimap.render(&self.map);
+        self.minimap = Some(minimap);
+      }
+    }
+    self.minimap.as_ref().unwrap().buffer_ptr()
+  }
+  /// Redraw the part of the minimap covering a cell after it has changed
+  #[wasm_bindgen(js_name = updateMinimap)]
+  pub fn js_update_minimap(&mut self, row: usize, col: usize) {
+    if let Some(minimap) = &mut self.minimap {
+      minimap.update_cell(&self.map, row, col);
+    }
+  }
 }
 
 impl fmt::Display for Game {
@@ -1,5 +1,6 @@
+use std::cmp::Reverse;
 use std::collections::VecDeque;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BinaryHeap, HashMap, HashSet};
 
 fn get_path(
   start: (usize, usize),
@@ -17,6 +18,7 @@ fn get_path(
 }
 
 // BFS to find the shortest path to a node
+#[allow(dead_code)]
 pub fn bfs_path_to_target<T, U>(
   start_cell: (usize, usize),
   get_neigbors: T,
@@ -54,6 +56,44 @@ where
   }
 }
 
+// Dijkstra to find the cheapest path to a node, `get_neighbors` gives each
+// neighbor with the cost of moving to it
+pub fn dijkstra_path_to_target<T, U>(
+  start_cell: (usize, usize),
+  get_neighbors: T,
+  is_match: U,
+) -> Option<Vec<(usize, usize)>>
+where
+  T: Fn((usize, usize)) -> Vec<((usize, usize), usize)>,
+  U: Fn((usize, usize)) -> bool,
+{
+  let mut queue = BinaryHeap::new();
+  let mut costs = HashMap::new();
+  let mut prev_map = HashMap::new();
+
+  queue.push(Reverse((0, start_cell)));
+  costs.insert(start_cell, 0);
+
+  while let Some(Reverse((cost, cell))) = queue.pop() {
+    // Skip cells already reached more cheaply
+    if costs.get(&cell).is_some_and(|&best| best < cost) {
+      continue;
+    }
+    if is_match(cell) {
+      return Some(get_path(cell, prev_map));
+    }
+    for (neighbor, step) in get_neighbors(cell) {
+      let next = cost + step;
+      if costs.get(&neighbor).is_none_or(|&best| next < best) {
+        costs.insert(neighbor, next);
+        prev_map.insert(neighbor, cell);
+        queue.push(Reverse((next, neighbor)));
+      }
+    }
+  }
+  None
+}
+
 #[cfg(test)]
 mod test {
   use super::*;
@@ -135,4 +175,39 @@ mod test {
     let path = bfs_path_to_target((0, 0), get_neighbors, is_target);
     assert_eq!(path, None);
   }
+
+  #[test]
+  fn find_cheapest_path() {
+    let col_count = 4;
+    #[rustfmt::skip]
+    let grid = vec![
+      1, 1, 1, 1,
+      1, 9, 9, 1,
+      1, 9, 1, 1,
+      1, 1, 1, 1,
+    ];
+    let get_neighbors = |(row, col): (usize, usize)| {
+      let mut neighbors = Vec::new();
+      if row > 0 {
+        neighbors.push((row - 1, col));
+      }
+      if col < col_count - 1 {
+        neighbors.push((row, col + 1));
+      }
+      if row < 3 {
+        neighbors.push((row + 1, col));
+      }
+      if col > 0 {
+        neighbors.push((row, col - 1));
+      }
+      neighbors
+        .into_iter()
+        .map(|(r, c)| ((r, c), grid[r * col_count + c]))
+        .collect()
+    };
+    // Around the expensive cells rather than through them
+    let path = dijkstra_path_to_target((1, 0), get_neighbors, |cell| cell == (2, 2));
+    assert_eq!(path.map(|path| path.len()), Some(6));
+    assert_eq!(dijkstra_path_to_target((0, 0), |_| vec![], |cell| cell == (2, 2)), None);
+  }
 }
@@ -0,0 +1,203 @@
+use crate::atlas::Tile;
+use crate::map::Map;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Water filling a closed basin
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Lake {
+  // Matches the values in `Map::lake_map`, starting from 1
+  pub id: u16,
+  // Height of the water surface
+  pub level: u8,
+  // Number of cells covered by water
+  pub area: usize,
+  // Lowest cell of the rim, where the lake would overflow
+  pub outlet: (usize, usize),
+}
+
+impl Map {
+  /// Id of the lake covering a cell, 0 if there is none
+  pub fn get_lake(&self, row: usize, col: usize) -> u16 {
+    self.lake_map[row * self.col_count + col]
+  }
+
+  pub fn lake_map_ptr(&self) -> *const u16 {
+    self.lake_map.as_ptr()
+  }
+
+  // Raise every cell to the lowest height water would have to reach to drain
+  // off the map or into existing water (priority-flood). Returns the filled
+  // heights and the cell each one was reached from.
+  fn flood(&self) -> (Vec<u8>, Vec<usize>) {
+    let size = self.row_count * self.col_count;
+    let mut filled = self.height_map.clone();
+    let mut parent = vec![usize::MAX; size];
+    let mut visited = vec![false; size];
+    // Ordered by height, then by the order cells were reached so ties are
+    // deterministic
+    let mut queue = BinaryHeap::new();
+    let mut order = 0;
+
+    for row in 0..self.row_count {
+      for col in 0..self.col_count {
+        let is_edge = row == 0 || row == self.row_count - 1 || col == 0 || col == self.col_count - 1;
+        let is_water = matches!(self.get_tile(row, col), Tile::Water | Tile::WaterDeep);
+        if is_edge || is_water {
+          let i = row * self.col_count + col;
+          visited[i] = true;
+          queue.push(Reverse((filled[i], order, i)));
+          order += 1;
+        }
+      }
+    }
+
+    while let Some(Reverse((level, _, i))) = queue.pop() {
+      for (row, col) in self.neighbors_4(i / self.col_count, i % self.col_count) {
+        let n = row * self.col_count + col;
+        if visited[n] {
+          continue;
+        }
+        visited[n] = true;
+        filled[n] = filled[n].max(level);
+        parent[n] = i;
+        queue.push(Reverse((filled[n], order, n)));
+        order += 1;
+      }
+    }
+    (filled, parent)
+  }
+
+  // Fill closed basins up to `max_depth` levels deep with water. The water
+  // surface sits one level below the rim, so the shore can be sloped.
+  pub(crate) fn fill_lakes(&mut self, max_depth: u8) {
+    let size = self.row_count * self.col_count;
+    self.lake_map = vec![0; size];
+    self.lakes = Vec::new();
+    if max_depth == 0 {
+      return;
+    }
+
+    let (filled, parent) = self.flood();
+    let heights = self.height_map.clone();
+    let is_basin = |i: usize| filled[i] > heights[i];
+    let mut seen = vec![false; size];
+
+    for start in 0..size {
+      if seen[start] || !is_basin(start) {
+        continue;
+      }
+      // Gather the connected cells sharing the same spill height
+      let spill = filled[start];
+      let mut basin = vec![start];
+      seen[start] = true;
+      let mut next = 0;
+      while next < basin.len() {
+        let i = basin[next];
+        next += 1;
+        for (row, col) in self.neighbors_4(i / self.col_count, i % self.col_count) {
+          let n = row * self.col_count + col;
+          if !seen[n] && is_basin(n) && filled[n] == spill {
+            seen[n] = true;
+            basin.push(n);
+          }
+        }
+      }
+
+      let bottom = basin.iter().map(|&i| heights[i]).min().unwrap_or(spill);
+      if spill - bottom > max_depth || self.lakes.len() >= u16::MAX as usize {
+        continue;
+      }
+      // The basin was first reached over its lowest rim cell
+      let outlet = basin
+        .iter()
+        .map(|&i| parent[i])
+        .find(|&p| p != usize::MAX && !is_basin(p) && heights[p] == spill)
+        .unwrap_or(start);
+
+      let lake = Lake {
+        id: self.lakes.len() as u16 + 1,
+        level: spill - 1,
+        area: basin.len(),
+        outlet: (outlet / self.col_count, outlet % self.col_count),
+      };
+      for &i in basin.iter() {
+        self.lake_map[i] = lake.id;
+        self.height_map[i] = lake.level;
+        self.tile_map[i] = Tile::Water;
+        self.tile_map_2[i] = Tile::Empty;
+      }
+      self.lakes.push(lake);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::atlas::Tile;
+  use crate::map::Map;
+  use crate::validate::ViolationKind;
+
+  fn bowl(depth: u8) -> Map {
+    let mut map = Map::new(5, 5);
+    #[rustfmt::skip]
+    let height_map = vec![
+      5, 5, 5, 5, 5,
+      5, 3, 3, 3, 5,
+      5, 3, 4 - depth, 3, 5,
+      5, 3, 3, 3, 4,
+      5, 5, 5, 5, 5,
+    ];
+    map.height_map = height_map;
+    map
+  }
+
+  #[test]
+  fn fills_closed_basins() {
+    let mut map = bowl(2);
+    map.fill_lakes(2);
+    assert_eq!(map.lakes.len(), 1);
+    let lake = &map.lakes[0];
+    assert_eq!(lake.level, 3);
+    assert_eq!(lake.area, 9);
+    assert_eq!(lake.outlet, (3, 4));
+    for row in 1..4 {
+      for col in 1..4 {
+        assert_eq!(map.get_lake(row, col), lake.id);
+        assert_eq!(map.get_height(row, col), 3);
+        assert_eq!(map.get_tile(row, col), Tile::Water);
+      }
+    }
+    assert_eq!(map.get_lake(0, 0), 0);
+    assert_eq!(map.get_tile(0, 0), Tile::Base);
+  }
+
+  #[test]
+  fn skips_deep_basins() {
+    let mut map = bowl(3);
+    map.fill_lakes(2);
+    assert!(map.lakes.is_empty());
+    assert!(map.lake_map.iter().all(|&id| id == 0));
+    assert_eq!(map.get_height(2, 2), 1);
+  }
+
+  #[test]
+  fn open_basins_drain() {
+    let mut map = bowl(2);
+    // Cut a channel so the basin drains off the map
+    map.set_height(2, 3, 2);
+    map.set_height(2, 4, 2);
+    map.fill_lakes(2);
+    assert!(map.lakes.is_empty());
+  }
+
+  #[test]
+  fn finds_leaking_lakes() {
+    let mut map = bowl(2);
+    map.fill_lakes(2);
+    assert_eq!(map.validate(), vec![]);
+    map.set_height(0, 2, 1);
+    let kinds = map.validate().iter().map(|v| v.kind).collect::<Vec<ViolationKind>>();
+    assert_eq!(kinds, vec![ViolationKind::LeakingLake(1)]);
+  }
+}
@@ -1,8 +1,38 @@
 mod atlas;
+mod bridge;
+mod collapse;
+mod config;
+mod erosion;
+mod forest;
 mod game;
 mod graph;
+mod lake;
 mod map;
+mod minimap;
+mod pipeline;
+mod poisson;
+mod prefab;
+mod region;
+mod resource;
+mod sampler;
+mod settlement;
+mod shape;
+mod snow;
+mod tunnel;
 mod utils;
+mod validate;
+mod world_code;
+
+pub use atlas::Tile;
+pub use config::{GenerationConfig, GenerationMode};
+pub use game::Game;
+pub use map::{Map, GENERATOR_VERSION};
+pub use pipeline::{GenerationContext, GenerationPass, Pipeline};
+pub use region::Region;
+pub use resource::Resource;
+pub use sampler::{Fractal, NoiseBasis};
+pub use settlement::Settlement;
+pub use shape::Shape;
 
 use wasm_bindgen::prelude::*;
 
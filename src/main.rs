@@ -1,13 +1,9 @@
-mod atlas;
-mod game;
-mod graph;
-mod map;
-mod utils;
+use wide_world::{Game, GenerationMode, Shape};
 
 const ROW_COUNT: usize = 64;
 const COL_COUNT: usize = 64;
 
 fn main() {
-  let game = game::Game::new(ROW_COUNT, COL_COUNT, 1000);
+  let game = Game::new(ROW_COUNT, COL_COUNT, "1000", Shape::Open, GenerationMode::Noise);
   println!("{}", game);
 }
@@ -1,9 +1,22 @@
 use crate::atlas::Tile;
-use crate::graph::bfs_path_to_target;
-use noise::{NoiseFn, Seedable, SuperSimplex};
+use crate::config::{GenerationConfig, GenerationMode};
+use crate::erosion::Heightfield;
+use crate::graph::dijkstra_path_to_target;
+use crate::lake::Lake;
+use crate::pipeline::Pipeline;
+use crate::poisson::poisson_disk;
+use crate::region::Region;
+use crate::resource::Resource;
+use crate::sampler::TerrainSampler;
+use crate::settlement::Settlement;
+use crate::shape::ShapeMask;
+use crate::utils::{derive_seed, hash_cell};
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
-use std::collections::HashSet;
+
+/// Bump whenever generation intentionally changes its output for an existing
+/// seed, size and config.
+pub const GENERATOR_VERSION: u32 = 22;
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 enum Direction {
@@ -20,7 +33,7 @@ enum Direction {
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
-enum Slope {
+pub(crate) enum Slope {
   North,
   East,
   South,
@@ -50,26 +63,36 @@ pub struct Map {
   pub tile_map_2: Vec<Tile>,
   // TODO: TileMap struct
   pub height_map: Vec<u8>,
+  // Lake id of each cell, 0 where there is no lake
+  pub lake_map: Vec<u16>,
+  pub lakes: Vec<Lake>,
+  // Region id of each cell, 0 for water
+  pub region_map: Vec<u16>,
+  pub regions: Vec<Region>,
+  // Resource in each cell and how much of it there is
+  pub resource_map: Vec<Resource>,
+  pub resource_amounts: Vec<u8>,
+  pub settlements: Vec<Settlement>,
+  pub config: GenerationConfig,
 }
 
 struct PlaceRandomConfig {
   max_count: usize,
-  min_distance: usize,
-  max_attempts: usize,
+  min_distance: f64,
   tile: Tile,
   seed: u64,
 }
 
-fn is_base_slope(tile: Tile) -> bool {
+pub(crate) fn is_base_slope(tile: Tile) -> bool {
   tile == Tile::BaseNorth
     || tile == Tile::BaseEast
     || tile == Tile::BaseSouth
     || tile == Tile::BaseWest
 }
 
-fn edge_slope(edge: (usize, usize), neighbors: &[(usize, usize)]) -> Slope {
-  let mut n_e_s_w = vec![false, false, false, false];
-  let mut ne_se_sw_nw = vec![false, false, false, false];
+pub(crate) fn edge_slope(edge: (usize, usize), neighbors: &[(usize, usize)]) -> Slope {
+  let mut n_e_s_w = [false, false, false, false];
+  let mut ne_se_sw_nw = [false, false, false, false];
 
   neighbors.iter().for_each(|&(n_row, n_col)| {
     if n_row < edge.0 && n_col == edge.1 {
@@ -150,6 +173,101 @@ fn edge_slope(edge: (usize, usize), neighbors: &[(usize, usize)]) -> Slope {
   Slope::Unknown
 }
 
+// The edge tile of a terrain for a slope. Base only has n/s/e/w slopes, other
+// slopes keep the flat tile.
+pub(crate) fn slope_tile(terrain: Tile, slope: Slope) -> Tile {
+  use Tile::*;
+  // In the order of `Slope`
+  let tiles = match terrain {
+    Base => [
+      BaseNorth, BaseEast, BaseSouth, BaseWest, Base, Base, Base, Base, Base, Base, Base, Base, Base, Base, Base,
+    ],
+    Cliff => [
+      CliffNorth,
+      CliffEast,
+      CliffSouth,
+      CliffWest,
+      CliffNorthEast1,
+      CliffSouthEast1,
+      CliffSouthWest1,
+      CliffNorthWest1,
+      CliffNorthEast3,
+      CliffSouthEast3,
+      CliffSouthWest3,
+      CliffNorthWest3,
+      CliffSaddleNorthEast,
+      CliffSaddleSouthEast,
+      Cliff,
+    ],
+    Rock => [
+      RockNorth,
+      RockEast,
+      RockSouth,
+      RockWest,
+      RockNorthEast1,
+      RockSouthEast1,
+      RockSouthWest1,
+      RockNorthWest1,
+      RockNorthEast3,
+      RockSouthEast3,
+      RockSouthWest3,
+      RockNorthWest3,
+      RockSaddleNorthEast,
+      RockSaddleSouthEast,
+      RockAlt,
+    ],
+    Marsh => [
+      MarshNorth,
+      MarshEast,
+      MarshSouth,
+      MarshWest,
+      MarshNorthEast1,
+      MarshSouthEast1,
+      MarshSouthWest1,
+      MarshNorthWest1,
+      MarshNorthEast3,
+      MarshSouthEast3,
+      MarshSouthWest3,
+      MarshNorthWest3,
+      MarshSaddleNorthEast,
+      MarshSaddleSouthEast,
+      MarshAlt,
+    ],
+    Sand => [
+      SandNorth,
+      SandEast,
+      SandSouth,
+      SandWest,
+      SandNorthEast1,
+      SandSouthEast1,
+      SandSouthWest1,
+      SandNorthWest1,
+      SandNorthEast3,
+      SandSouthEast3,
+      SandSouthWest3,
+      SandNorthWest3,
+      SandSaddleNorthEast,
+      SandSaddleSouthEast,
+      Sand,
+    ],
+    _ => return terrain,
+  };
+  tiles[slope as usize]
+}
+
+// North, east, south and west as (row, col) steps
+pub(crate) const STEPS: [(isize, isize); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+
+// Cells strictly between two cells in the same row or column, from the north
+// or west end
+pub(crate) fn cells_between(start: (usize, usize), end: (usize, usize)) -> Vec<(usize, usize)> {
+  if start.1 == end.1 {
+    (start.0.min(end.0) + 1..start.0.max(end.0)).map(|row| (row, start.1)).collect()
+  } else {
+    (start.1.min(end.1) + 1..start.1.max(end.1)).map(|col| (start.0, col)).collect()
+  }
+}
+
 fn path_direction(edge: (usize, usize), neighbors: Vec<(usize, usize)>) -> Direction {
   // Corner directions
   // NW x NE
@@ -193,7 +311,7 @@ fn path_direction(edge: (usize, usize), neighbors: Vec<(usize, usize)>) -> Direc
 }
 
 impl Map {
-  fn walkable_cells(&self, row: usize, col: usize) -> Vec<(usize, usize)> {
+  pub(crate) fn walkable_cells(&self, row: usize, col: usize) -> Vec<(usize, usize)> {
     let c_height = self.get_height(row, col);
     let c_tile = self.get_tile(row, col);
     self
@@ -201,6 +319,15 @@ impl Map {
       .iter()
       .cloned()
       .filter(|n| {
+        // Tunnels can only be walked along their length
+        if self.get_tile_2(row, col).is_tunnel() || self.get_tile_2(n.0, n.1).is_tunnel() {
+          return self.is_tunnel_link((row, col), *n);
+        }
+        // And so can bridges
+        if self.get_tile_2(row, col).is_bridge() || self.get_tile_2(n.0, n.1).is_bridge() {
+          return self.is_bridge_link((row, col), *n);
+        }
+
         // Find "walkable" neighbors
         // Is the neighbor north/south or east/west to the current cell?
         let n_is_n_or_s = (row as isize - n.0 as isize).abs() == 1 && col == n.1;
@@ -231,7 +358,7 @@ impl Map {
           return slope_check(n_tile, c_tile);
         }
 
-        if n_tile == Tile::Base && n_height == c_height {
+        if (n_tile == Tile::Base || n_tile == Tile::Sand) && n_height == c_height {
           // Neighbor is walkable
           return true;
         }
@@ -255,38 +382,29 @@ impl Map {
   fn can_place_patch(&self, row: usize, col: usize) -> bool {
     self.get_tile(row, col) == Tile::Base && self.get_tile_2(row, col) == Tile::Empty
   }
+  // Scatter a tile over the cells `can_place` allows, evenly spaced at least
+  // `min_distance` apart
   fn place_random<T>(&mut self, can_place: T, config: PlaceRandomConfig) -> Vec<(usize, usize)>
   where
     T: Fn(&Map, usize, usize) -> bool,
   {
     let mut rng = StdRng::seed_from_u64(config.seed);
-    let mut attempts = 0;
-    let mut placed: Vec<(usize, usize)> = Vec::new();
-    while placed.len() < config.max_count && attempts < config.max_attempts {
-      attempts += 1;
-      let row = rng.gen_range(0..self.row_count);
-      let col = rng.gen_range(0..self.col_count);
-      // Check if row and col are within min_distance
-      if placed
-        .iter()
-        .cloned()
-        .map(|(r, c)| (r as isize, c as isize))
-        .any(|(r, c)| {
-          (row as isize - r).abs() + (col as isize - c).abs() < config.min_distance as isize
-        })
-      {
-        // Placement is too close
-        continue;
-      }
-      if can_place(self, row, col) {
-        self.set_tile_2(row, col, config.tile);
-        placed.push((row, col));
-      }
+    let placed = poisson_disk(
+      self.row_count,
+      self.col_count,
+      config.min_distance,
+      config.max_count,
+      &mut rng,
+      |row, col| can_place(self, row, col),
+    );
+    for &(row, col) in placed.iter() {
+      self.set_tile_2(row, col, config.tile);
     }
     placed
   }
 
-  fn neighbors(&self, indices: Vec<(isize, isize)>) -> HashSet<(usize, usize)> {
+  // Neighbors are returned in a fixed order so generation is deterministic
+  fn neighbors(&self, indices: Vec<(isize, isize)>) -> Vec<(usize, usize)> {
     indices
       .iter()
       .cloned()
@@ -300,7 +418,7 @@ impl Map {
       .collect()
   }
 
-  fn neighbors_4(&self, row: usize, col: usize) -> HashSet<(usize, usize)> {
+  pub fn neighbors_4(&self, row: usize, col: usize) -> Vec<(usize, usize)> {
     let r = row as isize;
     let c = col as isize;
     // 0 x 0
@@ -310,7 +428,7 @@ impl Map {
     self.neighbors(indices)
   }
 
-  fn neighbors_8(&self, row: usize, col: usize) -> HashSet<(usize, usize)> {
+  pub fn neighbors_8(&self, row: usize, col: usize) -> Vec<(usize, usize)> {
     let r = row as isize;
     let c = col as isize;
     // x x x
@@ -329,6 +447,124 @@ impl Map {
     self.neighbors(indices)
   }
 
+  // Turn flat terrain into slopes, edges and cliffs to match the heights of
+  // the neighboring cells
+  pub(crate) fn terraform_cell(&mut self, row: usize, col: usize) {
+    let mut tile = self.get_tile(row, col);
+    // Cliffs where the ground drops by more than one level, these can't be
+    // walked like slopes
+    if tile == Tile::Base || tile == Tile::Rock || tile == Tile::Marsh {
+      let height = self.get_height(row, col);
+      let neighbors = self
+        .neighbors_8(row, col)
+        .iter()
+        .cloned()
+        .filter(|n| self.get_height(n.0, n.1) + 1 < height)
+        .collect::<Vec<(usize, usize)>>();
+
+      if !neighbors.is_empty() {
+        tile = slope_tile(Tile::Cliff, edge_slope((row, col), &neighbors));
+        self.set_tile(row, col, tile);
+      }
+    }
+
+    // Slope land
+    if tile == Tile::Base {
+      let neighbors = self
+        .neighbors_4(row, col)
+        .iter()
+        .cloned()
+        .filter(|n| {
+          let height = self.get_height(row, col);
+          let n_height = self.get_height(n.0, n.1);
+          n_height < height
+        })
+        .collect::<Vec<(usize, usize)>>();
+
+      // Only slope n/s/e/w tiles
+      if !neighbors.is_empty() {
+        tile = slope_tile(Tile::Base, edge_slope((row, col), &neighbors));
+        self.set_tile(row, col, tile);
+      }
+    }
+
+    // Slope mountains
+    if tile == Tile::Rock {
+      let neighbors = self
+        .neighbors_8(row, col)
+        .iter()
+        .cloned()
+        .filter(|n| {
+          let height = self.get_height(row, col);
+          let n_height = self.get_height(n.0, n.1);
+          n_height < height
+        })
+        .collect::<Vec<(usize, usize)>>();
+
+      if !neighbors.is_empty() {
+        tile = slope_tile(Tile::Rock, edge_slope((row, col), &neighbors));
+        self.set_tile(row, col, tile);
+      }
+    }
+
+    // Find marsh edges
+    if tile == Tile::Marsh {
+      let neighbors = self
+        .neighbors_8(row, col)
+        .iter()
+        .cloned()
+        .filter(|n| {
+          let height = self.get_height(row, col);
+          let n_height = self.get_height(n.0, n.1);
+          n_height < height
+        })
+        .collect::<Vec<(usize, usize)>>();
+
+      if !neighbors.is_empty() {
+        tile = slope_tile(Tile::Marsh, edge_slope((row, col), &neighbors));
+        self.set_tile(row, col, tile);
+      }
+    }
+
+    // Find beach edges
+    if tile == Tile::Sand {
+      let neighbors = self
+        .neighbors_8(row, col)
+        .iter()
+        .cloned()
+        .filter(|n| self.get_height(n.0, n.1) < self.get_height(row, col))
+        .collect::<Vec<(usize, usize)>>();
+
+      if !neighbors.is_empty() {
+        tile = slope_tile(Tile::Sand, edge_slope((row, col), &neighbors));
+        self.set_tile(row, col, tile);
+      }
+    }
+  }
+
+  // Turn land along the water's edge into beach, so the coast gets its own
+  // edge tiles. Drops of more than one level are left for cliffs.
+  fn place_shoreline(&mut self) {
+    for row in 0..self.row_count {
+      for col in 0..self.col_count {
+        let tile = self.get_tile(row, col);
+        if tile != Tile::Base && tile != Tile::Marsh {
+          continue;
+        }
+        let height = self.get_height(row, col);
+        let neighbors = self.neighbors_8(row, col);
+        let is_shore = neighbors.iter().any(|n| {
+          matches!(self.get_tile(n.0, n.1), Tile::Water | Tile::WaterDeep)
+            && self.get_height(n.0, n.1) + 1 == height
+        });
+        let is_steep = neighbors.iter().any(|n| self.get_height(n.0, n.1) + 1 < height);
+        if is_shore && !is_steep {
+          self.set_tile(row, col, Tile::Sand);
+        }
+      }
+    }
+  }
+
   fn is_invalid_tile(&self, row: usize, col: usize) -> bool {
     let height = self.get_height(row, col);
     // Get neighbors that are lower than the current tile
@@ -375,68 +611,129 @@ impl Map {
       height_map: vec![0; row_count * col_count],
       tile_map: vec![Tile::Base; row_count * col_count],
       tile_map_2: vec![Tile::Empty; row_count * col_count],
+      lake_map: vec![0; row_count * col_count],
+      lakes: Vec::new(),
+      region_map: vec![0; row_count * col_count],
+      regions: Vec::new(),
+      resource_map: vec![Resource::Empty; row_count * col_count],
+      resource_amounts: vec![0; row_count * col_count],
+      settlements: Vec::new(),
+      config: GenerationConfig::default(),
     }
   }
 
-  pub fn generate(&mut self, seed: u32) {
+  /// Generate the map with the standard passes, see `Pipeline`
+  pub fn generate(&mut self, seed: u64, config: &GenerationConfig) {
+    Pipeline::default().run(self, seed, config);
+  }
+
+  // Clear every layer before generating the map again
+  pub(crate) fn reset(&mut self, config: &GenerationConfig) {
     self.tile_map = vec![Tile::Base; self.row_count * self.col_count];
+    self.tile_map_2 = vec![Tile::Empty; self.row_count * self.col_count];
     self.height_map = vec![0; self.row_count * self.col_count];
+    self.lake_map = vec![0; self.row_count * self.col_count];
+    self.lakes = Vec::new();
+    self.region_map = vec![0; self.row_count * self.col_count];
+    self.regions = Vec::new();
+    self.resource_map = vec![Resource::Empty; self.row_count * self.col_count];
+    self.resource_amounts = vec![0; self.row_count * self.col_count];
+    self.settlements = Vec::new();
+    self.config = config.clone();
+  }
+
+  // Heights and terrain tiles, by the config's generation mode
+  pub(crate) fn generate_terrain(&mut self, seed: u64, config: &GenerationConfig) {
+    match config.mode {
+      GenerationMode::Noise => self.generate_noise(seed, config),
+      GenerationMode::Collapse => self.generate_collapse(seed),
+    }
+  }
+
+  // Rock landmarks, with a road from the first to the closest other one
+  pub(crate) fn place_landmarks(&mut self, seed: u64) {
+    let rocks = self.place_random(
+      Map::can_place_rocks,
+      PlaceRandomConfig {
+        max_count: (self.col_count / 16),
+        min_distance: self.config.landmark_spacing,
+        tile: Tile::Rocks,
+        seed,
+      },
+    );
+
+    if rocks.len() > 1 {
+      let start = rocks[0];
+      // Roads can bridge narrow water and tunnel through ridges
+      let neighbors_for_cell = |(row, col)| self.road_cells(row, col);
+      // Search for other rock tiles
+      let is_match = |(row, col)| (row, col) != start && self.get_tile_2(row, col) == Tile::Rocks;
 
-    let noise = SuperSimplex::default();
+      if let Some(path) = dijkstra_path_to_target(start, neighbors_for_cell, is_match) {
+        self.lay_road(&path);
+      }
+    }
+  }
 
-    web_sys::console::log_1(&format!("Seed: {}", seed).into());
+  pub(crate) fn place_patches(&mut self, seed: u64) {
+    self.place_random(
+      Map::can_place_patch,
+      PlaceRandomConfig {
+        max_count: (self.col_count / 4),
+        min_distance: self.config.patch_spacing,
+        tile: Tile::GrassPatch,
+        seed,
+      },
+    );
+  }
 
-    let n1 = noise.set_seed(seed);
-    let n2 = noise.set_seed(seed.saturating_add(1));
-    let n3 = noise.set_seed(seed.saturating_add(2));
-    let n4 = noise.set_seed(seed.saturating_add(3));
-    let mut rng = StdRng::seed_from_u64(seed as u64);
+  // Continuous heights from noise shaped by the mask, before erosion
+  pub(crate) fn noise_heightfield(&self, seed: u64, config: &GenerationConfig) -> Heightfield {
+    let terrain = TerrainSampler::new(seed, config);
+    let mut rng = StdRng::seed_from_u64(derive_seed(seed, "curve"));
 
-    let frequency = (self.col_count as f64 / 32.0).floor();
     let pow = rng.gen_range(1.0..1.4);
-    let get_noise = |n: &SuperSimplex, nx: f64, ny: f64| {
-      // Get noise value and scale to 0.0-1.0
-      n.get([nx, ny]) / 2.0 + 0.5
-    };
 
+    // The mask is applied before the curve, so find the elevation that will
+    // still be land after it
+    let land_min = config.land_min().powf(1.0 / pow);
+    let shape = ShapeMask::new(config.shape, self.row_count, self.col_count, seed, land_min);
+
+    let mut heightfield = Heightfield::new(self.row_count, self.col_count);
     (0..(self.row_count * self.col_count)).for_each(|i| {
       let col = i % self.col_count;
       let row = i / self.col_count;
 
-      let nx = col as f64 / self.col_count as f64 - 0.5;
-      let ny = row as f64 / self.row_count as f64 - 0.5;
-
-      let a1 = 1.0;
-      let a2 = 0.5;
-      let a3 = 0.25;
-
-      let mut val = a1 * get_noise(&n1, frequency * nx, frequency * ny)
-        + a2 * get_noise(&n2, frequency * 2.0 * nx, frequency * 2.0 * ny)
-        + a3 * get_noise(&n3, frequency * 3.0 * nx, frequency * 3.0 * ny);
-
-      // Add and divide by sum of aplitudes to get a value between 0 and 1
-      val /= a1 + a2 + a3;
-
       // Use curve to flatten value
       // Less than 1.0 creates more mountains, greater than 1.0 creates more water
-      val = val.powf(pow);
+      let elevation = shape.apply(terrain.elevation(row, col), row, col);
+      heightfield.values[i] = elevation.powf(pow) as f32;
+    });
+    heightfield
+  }
 
-      self.height_map[i] = match (val * 8.0).round() / 8.0 {
-        v if v < 0.25 => 1,
-        v if v < 0.5 => 2,
-        v if v < 0.75 => 3,
-        _ => 4,
-      };
+  // Heights from noise shaped by the mask, eroded and cut into terrain bands,
+  // with lakes in the basins
+  fn generate_noise(&mut self, seed: u64, config: &GenerationConfig) {
+    let mut heightfield = self.noise_heightfield(seed, config);
+
+    // Erode the continuous heights before they are quantized into levels
+    let mut erosion_rng = StdRng::seed_from_u64(derive_seed(seed, "erosion"));
+    heightfield.erode(
+      config.erosion_iterations,
+      config.erosion_strength,
+      &mut erosion_rng,
+    );
 
-      self.tile_map[i] = match (val * 8.0).round() / 8.0 {
-        v if v < 0.125 => Tile::WaterDeep,
-        v if v < 0.25 => Tile::Water,
-        v if v < 0.375 => Tile::Marsh,
-        v if v < 0.75 => Tile::Base,
-        _ => Tile::Rock,
-      };
+    (0..(self.row_count * self.col_count)).for_each(|i| {
+      let (height, tile) = config.quantize(heightfield.values[i] as f64);
+      self.height_map[i] = height;
+      self.tile_map[i] = tile;
     });
 
+    // Hold water in closed basins
+    self.fill_lakes(config.lake_depth);
+
     // Clean up "single" tiles, i.e. those that form a single line that
     // cannot be sloped
     (0..self.row_count).for_each(|row| {
@@ -446,8 +743,8 @@ impl Map {
           while let Some(n) = node {
             let height = self.get_height(n.0, n.1);
             let adjusted_height = height - 1;
-            let tile = match adjusted_height {
-              1 => Tile::Water,
+            let tile = match config.level_tile(adjusted_height) {
+              Tile::Water | Tile::WaterDeep => Tile::Water,
               _ => Tile::Base,
             };
             self.set_tile(n.0, n.1, tile);
@@ -463,223 +760,117 @@ impl Map {
       });
     });
 
+    self.place_shoreline();
+
     // Terraform
     (0..self.row_count).for_each(|row| {
       (0..self.col_count).for_each(|col| {
-        let mut tile = self.get_tile(row, col);
-        // Slope land
-        if tile == Tile::Base {
-          let neighbors = self
-            .neighbors_4(row, col)
-            .iter()
-            .cloned()
-            .filter(|n| {
-              let height = self.get_height(row, col);
-              let n_height = self.get_height(n.0, n.1);
-              n_height < height
-            })
-            .collect::<Vec<(usize, usize)>>();
-
-          // Only slope n/s/e/w tiles
-          if !neighbors.is_empty() {
-            let direction = edge_slope((row, col), &neighbors);
-            tile = match direction {
-              Slope::North => Tile::BaseNorth,
-              Slope::East => Tile::BaseEast,
-              Slope::South => Tile::BaseSouth,
-              Slope::West => Tile::BaseWest,
-              _ => tile,
-            };
-            self.set_tile(row, col, tile);
-          }
-        }
-
-        // Slope mountains
-        if tile == Tile::Rock {
-          let neighbors = self
-            .neighbors_8(row, col)
-            .iter()
-            .cloned()
-            .filter(|n| {
-              let height = self.get_height(row, col);
-              let n_height = self.get_height(n.0, n.1);
-              n_height < height
-            })
-            .collect::<Vec<(usize, usize)>>();
-
-          if !neighbors.is_empty() {
-            let direction = edge_slope((row, col), &neighbors);
-
-            tile = match direction {
-              Slope::NorthEast3 => Tile::RockNorthEast3,
-              Slope::NorthWest3 => Tile::RockNorthWest3,
-              Slope::SouthEast3 => Tile::RockSouthEast3,
-              Slope::SouthWest3 => Tile::RockSouthWest3,
-              Slope::NorthEast1 => Tile::RockNorthEast1,
-              Slope::NorthWest1 => Tile::RockNorthWest1,
-              Slope::SouthEast1 => Tile::RockSouthEast1,
-              Slope::SouthWest1 => Tile::RockSouthWest1,
-              Slope::SaddleNorthEast => Tile::RockSaddleNorthEast,
-              Slope::SaddleSouthEast => Tile::RockSaddleSouthEast,
-              Slope::North => Tile::RockNorth,
-              Slope::East => Tile::RockEast,
-              Slope::South => Tile::RockSouth,
-              Slope::West => Tile::RockWest,
-              Slope::Unknown => Tile::RockAlt,
-            };
-            self.set_tile(row, col, tile);
-          }
-        }
-
-        // Find marsh edges
-        if tile == Tile::Marsh {
-          let neighbors = self
-            .neighbors_8(row, col)
-            .iter()
-            .cloned()
-            .filter(|n| {
-              let height = self.get_height(row, col);
-              let n_height = self.get_height(n.0, n.1);
-              n_height < height
-            })
-            .collect::<Vec<(usize, usize)>>();
-
-          if !neighbors.is_empty() {
-            let direction = edge_slope((row, col), &neighbors);
-
-            tile = match direction {
-              Slope::NorthEast3 => Tile::MarshNorthEast3,
-              Slope::NorthWest3 => Tile::MarshNorthWest3,
-              Slope::SouthEast3 => Tile::MarshSouthEast3,
-              Slope::SouthWest3 => Tile::MarshSouthWest3,
-              Slope::NorthEast1 => Tile::MarshNorthEast1,
-              Slope::NorthWest1 => Tile::MarshNorthWest1,
-              Slope::SouthEast1 => Tile::MarshSouthEast1,
-              Slope::SouthWest1 => Tile::MarshSouthWest1,
-              Slope::SaddleNorthEast => Tile::MarshSaddleNorthEast,
-              Slope::SaddleSouthEast => Tile::MarshSaddleSouthEast,
-              Slope::North => Tile::MarshNorth,
-              Slope::East => Tile::MarshEast,
-              Slope::South => Tile::MarshSouth,
-              Slope::West => Tile::MarshWest,
-              Slope::Unknown => Tile::MarshAlt,
-            };
-            self.set_tile(row, col, tile);
-          }
-        }
-
-        // Add flora
-        let nx = col as f64 / self.col_count as f64 - 0.5;
-        let ny = row as f64 / self.row_count as f64 - 0.5;
-        // Add trees
-        if tile == Tile::Base {
-          let height = self.get_height(row, col);
-          let random = get_noise(&n4, nx * 3.0, ny * 3.0);
-          self.set_tile_2(
-            row,
-            col,
-            match random {
-              // Higher chance of trees at lower altitudes
-              r if height == 2 && r < 0.3 => Tile::Tree,
-              r if height == 3 && r < 0.2 => Tile::Tree,
-              r if height == 4 && r < 0.1 => Tile::Tree,
-              _ => Tile::Empty,
-            },
-          );
-        }
-        /*
-        // Add reeds
-        if tile == Tile::Marsh {
-          let random = get_noise(&n4, nx * 3.0, ny * 3.0);
-          // Add marsh
-          self.set_tile_2(
-            row,
-            col,
-            match random {
-              // Higher chance of reeds at lower altitudes
-              r if r >= 0.4 => Tile::Reeds,
-              _ => Tile::Empty,
-            },
-          );
-        }
-        */
+        self.terraform_cell(row, col);
       });
     });
+  }
 
-    let rocks = self.place_random(
-      Map::can_place_rocks,
-      PlaceRandomConfig {
-        max_count: (self.col_count / 16),
-        min_distance: 40,
-        max_attempts: self.col_count,
-        tile: Tile::Rocks,
-        seed: seed as u64,
-      },
-    );
-
-    if rocks.len() > 1 {
-      let start = rocks[0];
-      let neighbors_for_cell = |(row, col)| self.walkable_cells(row, col);
-      // Search for other rock tiles
-      let is_match = |(row, col)| (row, col) != start && self.get_tile_2(row, col) == Tile::Rocks;
+  // Turn a path into road tiles, leaving out the first and last cells, which
+  // are the places the road connects
+  pub(crate) fn lay_road(&mut self, path: &[(usize, usize)]) {
+    for i in 1..path.len().saturating_sub(1) {
+      let cell = path[i];
+      // The road runs through tunnels already there
+      if self.get_tile_2(cell.0, cell.1).is_tunnel() {
+        continue;
+      }
+      let mut tile = match self.get_tile(cell.0, cell.1) {
+        Tile::BaseNorth => Tile::RoadSlopeNorth,
+        Tile::BaseEast => Tile::RoadSlopeEast,
+        Tile::BaseSouth => Tile::RoadSlopeSouth,
+        Tile::BaseWest => Tile::RoadSlopeWest,
+        _ => Tile::Road1,
+      };
+      if tile == Tile::Road1 {
+        let direction = path_direction(cell, vec![path[i - 1], path[i + 1]]);
+        tile = match direction {
+          Direction::NorthEast => Tile::RoadNorthEast,
+          Direction::NorthWest => Tile::RoadNorthWest,
+          Direction::SouthEast => Tile::RoadSouthEast,
+          Direction::SouthWest => Tile::RoadSouthWest,
+          Direction::Lat => Tile::Road2,
+          _ => tile,
+        };
+      }
+      self.set_tile(cell.0, cell.1, tile);
+      self.set_tile_2(cell.0, cell.1, Tile::Empty);
+    }
+    // Cells further apart than neighbors are the ends of a bridge over lower
+    // ground, or a tunnel through higher ground
+    for pair in path.windows(2) {
+      let (a, b) = (pair[0], pair[1]);
+      if a.0.max(b.0) - a.0.min(b.0) + a.1.max(b.1) - a.1.min(b.1) > 1 {
+        let next = if a.0 == b.0 {
+          (a.0, if b.1 > a.1 { a.1 + 1 } else { a.1 - 1 })
+        } else {
+          (if b.0 > a.0 { a.0 + 1 } else { a.0 - 1 }, a.1)
+        };
+        if self.get_height(next.0, next.1) > self.get_height(a.0, a.1) {
+          self.build_tunnel(a, b);
+        } else {
+          self.build_bridge(a, b);
+        }
+      }
+    }
+  }
 
-      if let Some(path) = bfs_path_to_target(start, neighbors_for_cell, is_match) {
-        // Remove first and last element from path
-        let slice = &path[1..path.len() - 1];
-        for i in 0..slice.len() {
-          let cell = slice[i];
-          let mut neighbors = Vec::new();
-          if i > 0 {
-            neighbors.push(slice[i - 1]);
-          }
-          if i < slice.len() - 1 {
-            neighbors.push(slice[i + 1]);
-          }
-          let mut tile = match self.get_tile(cell.0, cell.1) {
-            Tile::BaseNorth => Tile::RoadSlopeNorth,
-            Tile::BaseEast => Tile::RoadSlopeEast,
-            Tile::BaseSouth => Tile::RoadSlopeSouth,
-            Tile::BaseWest => Tile::RoadSlopeWest,
-            _ => Tile::Road1,
-          };
-          if tile == Tile::Road1 {
-            let direction = path_direction(cell, neighbors);
-            tile = match direction {
-              Direction::NorthEast => Tile::RoadNorthEast,
-              Direction::NorthWest => Tile::RoadNorthWest,
-              Direction::SouthEast => Tile::RoadSouthEast,
-              Direction::SouthWest => Tile::RoadSouthWest,
-              Direction::Lat => Tile::Road2,
-              _ => tile,
-            };
+  // Reeds in marshes and water lilies in the shallows along the shore, on
+  // about `density` of the cells that can hold them. Cells already holding
+  // something, like roads or landmarks, are left alone.
+  pub(crate) fn place_wetland_flora(&mut self, seed: u64, density: f64) {
+    for row in 0..self.row_count {
+      for col in 0..self.col_count {
+        let tile = self.get_tile(row, col);
+        if self.get_tile_2(row, col) != Tile::Empty || tile.is_road() {
+          continue;
+        }
+        let flora = match tile {
+          t if t.terrain() == Tile::Marsh => Tile::Reeds,
+          Tile::Water
+            if self.neighbors_8(row, col).iter().any(|n| {
+              !matches!(self.get_tile(n.0, n.1), Tile::Water | Tile::WaterDeep)
+            }) =>
+          {
+            Tile::WaterLily
           }
-          self.set_tile(cell.0, cell.1, tile);
-          self.set_tile_2(cell.0, cell.1, Tile::Empty);
+          _ => continue,
+        };
+        if (hash_cell(seed, row, col) as f64 / u64::MAX as f64) < density {
+          self.set_tile_2(row, col, flora);
         }
       }
     }
+  }
 
-    self.place_random(
-      Map::can_place_patch,
-      PlaceRandomConfig {
-        max_count: (self.col_count / 4),
-        min_distance: 20,
-        max_attempts: (self.col_count / 4) * 2,
-        tile: Tile::GrassPatch,
-        seed: seed as u64,
-      },
-    );
+  // Swap tiles for one of their visual variants. Runs last so earlier stages
+  // only ever see the base tiles.
+  pub(crate) fn apply_variants(&mut self, seed: u64) {
+    for row in 0..self.row_count {
+      for col in 0..self.col_count {
+        let tile = self.get_tile(row, col);
+        self.set_tile(row, col, tile.variant(hash_cell(seed, row, col)));
+        let tile_2 = self.get_tile_2(row, col);
+        // Use a different seed for the second layer so variants on the two
+        // layers are not correlated
+        self.set_tile_2(row, col, tile_2.variant(hash_cell(!seed, row, col)));
+      }
+    }
   }
 
-  fn in_bounds(&self, row: isize, col: isize) -> bool {
+  pub(crate) fn in_bounds(&self, row: isize, col: isize) -> bool {
     (row >= 0 && row < self.row_count as isize) && (col >= 0 && col < self.col_count as isize)
   }
 
-  fn get_tile(&self, row: usize, col: usize) -> Tile {
+  pub fn get_tile(&self, row: usize, col: usize) -> Tile {
     self.tile_map[row * self.col_count + col]
   }
 
-  fn set_tile(&mut self, row: usize, col: usize, tile: Tile) {
+  pub fn set_tile(&mut self, row: usize, col: usize, tile: Tile) {
     self.tile_map[row * self.col_count + col] = tile;
   }
 
@@ -687,11 +878,11 @@ impl Map {
     self.tile_map.as_ptr()
   }
 
-  fn get_tile_2(&self, row: usize, col: usize) -> Tile {
+  pub fn get_tile_2(&self, row: usize, col: usize) -> Tile {
     self.tile_map_2[row * self.col_count + col]
   }
 
-  fn set_tile_2(&mut self, row: usize, col: usize, tile: Tile) {
+  pub fn set_tile_2(&mut self, row: usize, col: usize, tile: Tile) {
     self.tile_map_2[row * self.col_count + col] = tile;
   }
 
@@ -699,11 +890,11 @@ impl Map {
     self.tile_map_2.as_ptr()
   }
 
-  fn get_height(&self, row: usize, col: usize) -> u8 {
+  pub fn get_height(&self, row: usize, col: usize) -> u8 {
     self.height_map[row * self.col_count + col]
   }
 
-  fn set_height(&mut self, row: usize, col: usize, height: u8) {
+  pub fn set_height(&mut self, row: usize, col: usize, height: u8) {
     self.height_map[row * self.col_count + col] = height;
   }
 
@@ -760,6 +951,107 @@ mod test {
   }
 
   #[test]
+  fn cliffs_on_drops_of_more_than_one_level() {
+    let mut map = Map::new(3, 3);
+    #[rustfmt::skip]
+    let height_map = vec![
+      1, 1, 1,
+      3, 3, 3,
+      4, 4, 4,
+    ];
+    map.height_map = height_map;
+    for row in 0..3 {
+      for col in 0..3 {
+        map.terraform_cell(row, col);
+      }
+    }
+    // Drops of two levels are cliffs, one level drops are slopes
+    assert_eq!(map.get_tile(1, 1), Tile::CliffNorth);
+    assert_eq!(map.get_tile(2, 1), Tile::BaseNorth);
+    assert_eq!(map.walkable_cells(2, 1), vec![]);
+    assert_eq!(map.validate(), vec![]);
+  }
+
+  #[test]
+  fn beaches_along_water() {
+    let mut map = Map::new(3, 3);
+    #[rustfmt::skip]
+    let height_map = vec![
+      1, 1, 2,
+      2, 2, 2,
+      2, 2, 2,
+    ];
+    map.height_map = height_map;
+    map.tile_map[0] = Tile::Water;
+    map.tile_map[1] = Tile::Water;
+    map.place_shoreline();
+    for row in 0..3 {
+      for col in 0..3 {
+        map.terraform_cell(row, col);
+      }
+    }
+    assert_eq!(map.get_tile(1, 0), Tile::SandNorth);
+    assert_eq!(map.get_tile(1, 1), Tile::SandNorth);
+    assert_eq!(map.get_tile(1, 2), Tile::SandNorthWest3);
+    assert_eq!(map.get_tile(2, 1), Tile::Base);
+    assert_eq!(map.validate(), vec![]);
+  }
+
+  #[test]
+  fn wetland_flora() {
+    let mut map = Map::new(3, 3);
+    #[rustfmt::skip]
+    let tile_map = vec![
+      Tile::Marsh, Tile::Marsh, Tile::Marsh,
+      Tile::Water, Tile::Water, Tile::Base,
+      Tile::Water, Tile::WaterDeep, Tile::Road1,
+    ];
+    map.tile_map = tile_map;
+    map.set_tile_2(0, 2, Tile::Rocks);
+    map.place_wetland_flora(1, 0.0);
+    assert!(map.tile_map_2.iter().all(|&t| t == Tile::Empty || t == Tile::Rocks));
+    map.place_wetland_flora(1, 1.0);
+    #[rustfmt::skip]
+    let tile_map_2 = vec![
+      Tile::Reeds, Tile::Reeds, Tile::Rocks,
+      Tile::WaterLily, Tile::WaterLily, Tile::Empty,
+      Tile::Empty, Tile::Empty, Tile::Empty,
+    ];
+    assert_eq!(map.tile_map_2, tile_map_2);
+  }
+
+  #[test]
+  fn variants_are_stable() {
+    let mut map = Map::new(8, 8);
+    map.tile_map = vec![Tile::Marsh; 64];
+    map.tile_map_2 = vec![Tile::Rock; 64];
+    map.apply_variants(42);
+    let mut other = Map::new(8, 8);
+    other.tile_map = vec![Tile::Marsh; 64];
+    other.tile_map_2 = vec![Tile::Rock; 64];
+    other.apply_variants(42);
+    assert_eq!(map.tile_map, other.tile_map);
+    assert_eq!(map.tile_map_2, other.tile_map_2);
+    assert!(map
+      .tile_map
+      .iter()
+      .all(|&t| t == Tile::Marsh || t == Tile::MarshAlt));
+    assert!(map.tile_map.contains(&Tile::MarshAlt));
+    #[rustfmt::skip]
+    let first_row = [
+      Tile::Rock, Tile::RockAlt, Tile::Rock, Tile::RockAlt,
+      Tile::Rock, Tile::RockAlt, Tile::Rock, Tile::Rock,
+    ];
+    assert_eq!(map.tile_map_2[..8], first_row[..]);
+    // The second layer uses different variants
+    assert_ne!(
+      map.tile_map.iter().map(|&t| t == Tile::MarshAlt).collect::<Vec<bool>>(),
+      map.tile_map_2.iter().map(|&t| t == Tile::RockAlt).collect::<Vec<bool>>()
+    );
+  }
+
+  #[test]
+  #[allow(clippy::useless_vec)]
   fn find_edge_slope() {
     let edge = (1, 1);
     // - - -
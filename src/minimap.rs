@@ -0,0 +1,247 @@
+use crate::atlas::Tile;
+use crate::map::Map;
+
+// Light comes from the north west, so cells higher than their north west
+// neighbor are lit and lower cells are in shade
+const SHADE_STEP: f32 = 0.18;
+
+pub struct Minimap {
+  pub width: usize,
+  pub height: usize,
+  // RGBA
+  pub buffer: Vec<u8>,
+}
+
+fn tile_color(tile: Tile) -> Option<[u8; 3]> {
+  match tile {
+    Tile::Base | Tile::BaseNorth | Tile::BaseEast | Tile::BaseSouth | Tile::BaseWest => {
+      Some([106, 168, 79])
+    }
+    Tile::Marsh
+    | Tile::MarshAlt
+    | Tile::MarshNorth
+    | Tile::MarshEast
+    | Tile::MarshSouth
+    | Tile::MarshWest
+    | Tile::MarshNorthEast1
+    | Tile::MarshSouthEast1
+    | Tile::MarshSouthWest1
+    | Tile::MarshNorthWest1
+    | Tile::MarshNorthEast3
+    | Tile::MarshSouthEast3
+    | Tile::MarshSouthWest3
+    | Tile::MarshNorthWest3
+    | Tile::MarshSaddleNorthEast
+    | Tile::MarshSaddleSouthEast => Some([96, 128, 72]),
+    Tile::Rock
+    | Tile::RockAlt
+    | Tile::RockNorth
+    | Tile::RockEast
+    | Tile::RockSouth
+    | Tile::RockWest
+    | Tile::RockNorthEast1
+    | Tile::RockSouthEast1
+    | Tile::RockSouthWest1
+    | Tile::RockNorthWest1
+    | Tile::RockNorthEast3
+    | Tile::RockSouthEast3
+    | Tile::RockSouthWest3
+    | Tile::RockNorthWest3
+    | Tile::RockSaddleNorthEast
+    | Tile::RockSaddleSouthEast => Some([128, 122, 112]),
+    Tile::Ice => Some([196, 222, 236]),
+    t if t.is_snow() => Some([236, 240, 244]),
+    t if t.terrain() == Tile::Cliff => Some([100, 90, 80]),
+    t if t.terrain() == Tile::Sand => Some([222, 204, 150]),
+    Tile::WaterDeep => Some([30, 70, 140]),
+    Tile::Water => Some([52, 110, 180]),
+    t if t.is_road() => Some([196, 164, 112]),
+    t if t.is_bridge() => Some([150, 108, 64]),
+    // Darker than the road, as it runs under the rock
+    t if t.is_tunnel() => Some([88, 76, 64]),
+    Tile::Tree | Tile::TreeAlt => Some([46, 100, 46]),
+    Tile::Rocks => Some([160, 156, 150]),
+    Tile::Reeds => Some([120, 150, 80]),
+    Tile::WaterLily => Some([80, 150, 110]),
+    Tile::GrassPatch => Some([130, 190, 90]),
+    t if t.is_building() => Some([172, 78, 58]),
+    Tile::Ruin | Tile::RuinPillar => Some([150, 140, 118]),
+    Tile::Tent => Some([210, 196, 160]),
+    Tile::Campfire => Some([232, 120, 40]),
+    Tile::Shrine => Some([226, 210, 120]),
+    Tile::Debug => Some([255, 0, 255]),
+    _ => None,
+  }
+}
+
+impl Minimap {
+  pub fn new(width: usize, height: usize) -> Minimap {
+    Minimap {
+      width,
+      height,
+      buffer: vec![0; width * height * 4],
+    }
+  }
+
+  pub fn buffer_ptr(&self) -> *const u8 {
+    self.buffer.as_ptr()
+  }
+
+  // An empty map has nothing to draw, the minimap stays blank
+  pub fn render(&mut self, map: &Map) {
+    if map.row_count == 0 || map.col_count == 0 {
+      return;
+    }
+    for y in 0..self.height {
+      for x in 0..self.width {
+        self.render_pixel(map, x, y);
+      }
+    }
+  }
+
+  // Redraw only the pixels affected by a change to a cell. The cell to the
+  // south east is included since its shading depends on this cell.
+  pub fn update_cell(&mut self, map: &Map, row: usize, col: usize) {
+    if row >= map.row_count || col >= map.col_count {
+      return;
+    }
+    let row_end = (row + 2).min(map.row_count);
+    let col_end = (col + 2).min(map.col_count);
+    let (y_start, y_end) = pixel_range(row, row_end, map.row_count, self.height);
+    let (x_start, x_end) = pixel_range(col, col_end, map.col_count, self.width);
+    for y in y_start..y_end {
+      for x in x_start..x_end {
+        self.render_pixel(map, x, y);
+      }
+    }
+  }
+
+  fn render_pixel(&mut self, map: &Map, x: usize, y: usize) {
+    let (row_start, row_end) = cell_range(y, self.height, map.row_count);
+    let (col_start, col_end) = cell_range(x, self.width, map.col_count);
+
+    // Roads and the bridges and tunnels they take are drawn over everything
+    // else so they stay visible when the map is scaled down
+    let mut road = None;
+    for row in row_start..row_end {
+      for col in col_start..col_end {
+        let i = row * map.col_count + col;
+        let tile_2 = map.tile_map_2[i];
+        if map.tile_map[i].is_road() || tile_2.is_bridge() || tile_2.is_tunnel() {
+          road = Some((row, col));
+        }
+      }
+    }
+
+    // Sample the center of the area covered by the pixel
+    let (row, col) = road.unwrap_or(((row_start + row_end) / 2, (col_start + col_end) / 2));
+    let i = row * map.col_count + col;
+    let tile = map.tile_map[i];
+    let tile_2 = map.tile_map_2[i];
+    let color = match road {
+      Some(_) if tile_2.is_bridge() || tile_2.is_tunnel() => tile_color(tile_2),
+      Some(_) => tile_color(tile),
+      None => tile_color(tile_2).or_else(|| tile_color(tile)),
+    }
+    .unwrap_or([0, 0, 0]);
+
+    let height = map.height_map[i] as f32;
+    let nw_height = if row > 0 && col > 0 {
+      map.height_map[(row - 1) * map.col_count + col - 1] as f32
+    } else {
+      height
+    };
+    let shade = (1.0 + (height - nw_height) * SHADE_STEP).max(0.0);
+
+    let p = (y * self.width + x) * 4;
+    for (c, value) in color.iter().enumerate() {
+      self.buffer[p + c] = (*value as f32 * shade).min(255.0) as u8;
+    }
+    self.buffer[p + 3] = 255;
+  }
+}
+
+// Cells covered by a pixel. Always at least one cell, unless there are no
+// cells at all.
+fn cell_range(pixel: usize, pixel_count: usize, cell_count: usize) -> (usize, usize) {
+  let start = pixel * cell_count / pixel_count;
+  let end = ((pixel + 1) * cell_count / pixel_count).max(start + 1);
+  (start.min(cell_count.saturating_sub(1)), end.min(cell_count))
+}
+
+// Pixels whose area includes any of the cells in start..end
+fn pixel_range(start: usize, end: usize, cell_count: usize, pixel_count: usize) -> (usize, usize) {
+  let pixel_start = start * pixel_count / cell_count;
+  let pixel_end = (end * pixel_count).div_ceil(cell_count);
+  (pixel_start, pixel_end.min(pixel_count))
+}
+
+#[cfg(test)]
+mod test {
+  use super::{cell_range, tile_color, Minimap};
+  use crate::atlas::Tile;
+  use crate::map::Map;
+
+  #[test]
+  fn update_cell_matches_full_render() {
+    for &(width, height) in &[(4, 4), (8, 8), (5, 3), (16, 16)] {
+      let mut map = Map::new(8, 8);
+      let mut minimap = Minimap::new(width, height);
+      minimap.render(&map);
+
+      map.tile_map[3 * 8 + 4] = Tile::Road1;
+      map.height_map[3 * 8 + 4] = 2;
+      minimap.update_cell(&map, 3, 4);
+
+      let mut expected = Minimap::new(width, height);
+      expected.render(&map);
+      assert_eq!(minimap.buffer, expected.buffer);
+    }
+  }
+
+  #[test]
+  fn empty_maps_render_blank() {
+    assert_eq!(cell_range(0, 4, 0), (0, 0));
+    for &(rows, cols) in &[(0, 0), (0, 8), (8, 0)] {
+      let map = Map::new(rows, cols);
+      let mut minimap = Minimap::new(4, 4);
+      minimap.render(&map);
+      minimap.update_cell(&map, 0, 0);
+      assert!(minimap.buffer.iter().all(|&value| value == 0));
+    }
+  }
+
+  #[test]
+  fn bridges_show_like_roads() {
+    let mut map = Map::new(8, 8);
+    map.tile_map = vec![Tile::Water; 64];
+    map.tile_map_2[3 * 8 + 4] = Tile::Bridge2;
+    // Scaled down, the bridge still shows over the water around it
+    let mut minimap = Minimap::new(2, 2);
+    minimap.render(&map);
+    let color = tile_color(Tile::Bridge2).unwrap();
+    assert_eq!(minimap.buffer[4..7], color);
+  }
+
+  #[test]
+  fn tunnels_show_through_the_rock() {
+    let mut map = Map::new(8, 8);
+    map.tile_map = vec![Tile::Rock; 64];
+    map.tile_map_2[3 * 8 + 4] = Tile::Tunnel2;
+    let mut minimap = Minimap::new(2, 2);
+    minimap.render(&map);
+    let color = tile_color(Tile::Tunnel2).unwrap();
+    assert_eq!(minimap.buffer[4..7], color);
+  }
+
+  #[test]
+  fn objects_have_colors() {
+    for tile in [Tile::House, Tile::BarnWest, Tile::HallNorthWest, Tile::HallSouthEast].iter() {
+      assert_eq!(tile_color(*tile), tile_color(Tile::House), "{:?}", tile);
+    }
+    assert_ne!(tile_color(Tile::House), tile_color(Tile::Base));
+    for tile in [Tile::Ruin, Tile::RuinPillar, Tile::Tent, Tile::Campfire, Tile::Shrine].iter() {
+      assert!(tile_color(*tile).is_some(), "{:?}", tile);
+    }
+  }
+}
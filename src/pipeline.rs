@@ -0,0 +1,227 @@
+use crate::config::GenerationConfig;
+use crate::map::Map;
+use crate::utils::derive_seed;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// What the passes of a pipeline work on
+pub struct GenerationContext<'a> {
+  pub map: &'a mut Map,
+  pub config: &'a GenerationConfig,
+  // The world seed
+  pub seed: u64,
+}
+
+impl GenerationContext<'_> {
+  /// Seed for a named stream of random numbers. Each pass should use its own
+  /// streams, so adding or removing a pass doesn't change what the others
+  /// generate.
+  pub fn stream_seed(&self, name: &str) -> u64 {
+    derive_seed(self.seed, name)
+  }
+
+  pub fn rng(&self, name: &str) -> StdRng {
+    StdRng::seed_from_u64(self.stream_seed(name))
+  }
+}
+
+/// One step of map generation
+pub trait GenerationPass {
+  /// Name the pass is found by in a pipeline
+  fn name(&self) -> &str;
+  fn run(&self, context: &mut GenerationContext);
+}
+
+// A built-in pass
+struct Stage {
+  name: &'static str,
+  run: fn(&mut GenerationContext),
+}
+
+impl GenerationPass for Stage {
+  fn name(&self) -> &str {
+    self.name
+  }
+
+  fn run(&self, context: &mut GenerationContext) {
+    (self.run)(context)
+  }
+}
+
+/// Passes run in order to generate a map. `Pipeline::default()` has the
+/// standard passes: terrain, landmarks, forests, settlements, prefabs,
+/// regions, wetland, patches, resources, snow and variants.
+pub struct Pipeline {
+  passes: Vec<Box<dyn GenerationPass>>,
+}
+
+impl Default for Pipeline {
+  fn default() -> Pipeline {
+    let stages: [Stage; 11] = [
+      Stage {
+        name: "terrain",
+        run: |context| context.map.generate_terrain(context.seed, context.config),
+      },
+      // Rocks and the road between them
+      Stage {
+        name: "landmarks",
+        run: |context| context.map.place_landmarks(context.stream_seed("rocks")),
+      },
+      Stage {
+        name: "forests",
+        run: |context| context.map.grow_forests(context.stream_seed("forest")),
+      },
+      Stage {
+        name: "settlements",
+        run: |context| context.map.place_settlements(context.stream_seed("settlements")),
+      },
+      // Ruins, camps and shrines
+      Stage {
+        name: "prefabs",
+        run: |context| context.map.place_prefabs(context.stream_seed("prefabs")),
+      },
+      // After every pass that changes the terrain
+      Stage {
+        name: "regions",
+        run: |context| context.map.partition_regions(context.stream_seed("regions")),
+      },
+      // Reeds and water lilies, on their own stream so they don't follow the
+      // trees
+      Stage {
+        name: "wetland",
+        run: |context| {
+          let seed = context.stream_seed("wetland");
+          context.map.place_wetland_flora(seed, context.config.wetland_density);
+        },
+      },
+      Stage {
+        name: "patches",
+        run: |context| context.map.place_patches(context.stream_seed("patches")),
+      },
+      // Ore, clay, fish and timber, once the trees have grown
+      Stage {
+        name: "resources",
+        run: |context| context.map.place_resources(context.stream_seed("resources")),
+      },
+      Stage {
+        name: "snow",
+        run: |context| context.map.cap_snow(),
+      },
+      // Last, so earlier passes only ever see the base tiles
+      Stage {
+        name: "variants",
+        run: |context| context.map.apply_variants(context.stream_seed("variants")),
+      },
+    ];
+    let mut pipeline = Pipeline::empty();
+    for stage in stages {
+      pipeline.push(stage);
+    }
+    pipeline
+  }
+}
+
+impl Pipeline {
+  pub fn empty() -> Pipeline {
+    Pipeline { passes: Vec::new() }
+  }
+
+  pub fn names(&self) -> Vec<&str> {
+    self.passes.iter().map(|pass| pass.name()).collect()
+  }
+
+  fn position(&self, name: &str) -> Result<usize, String> {
+    self
+      .passes
+      .iter()
+      .position(|pass| pass.name() == name)
+      .ok_or(format!("No pass named {}", name))
+  }
+
+  /// Add a pass to run after all the others
+  pub fn push<T: GenerationPass + 'static>(&mut self, pass: T) {
+    self.passes.push(Box::new(pass));
+  }
+
+  pub fn insert_before<T: GenerationPass + 'static>(&mut self, name: &str, pass: T) -> Result<(), String> {
+    let i = self.position(name)?;
+    self.passes.insert(i, Box::new(pass));
+    Ok(())
+  }
+
+  pub fn insert_after<T: GenerationPass + 'static>(&mut self, name: &str, pass: T) -> Result<(), String> {
+    let i = self.position(name)?;
+    self.passes.insert(i + 1, Box::new(pass));
+    Ok(())
+  }
+
+  /// Take a pass out of the pipeline, e.g. to disable it or run it elsewhere
+  pub fn remove(&mut self, name: &str) -> Result<Box<dyn GenerationPass>, String> {
+    let i = self.position(name)?;
+    Ok(self.passes.remove(i))
+  }
+
+  /// Clear the map and run every pass over it in order
+  pub fn run(&self, map: &mut Map, seed: u64, config: &GenerationConfig) {
+    map.reset(config);
+
+    #[cfg(target_arch = "wasm32")]
+    web_sys::console::log_1(&format!("Seed: {}", seed).into());
+
+    let mut context = GenerationContext { map, config, seed };
+    for pass in self.passes.iter() {
+      pass.run(&mut context);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{GenerationContext, GenerationPass, Pipeline};
+  use crate::atlas::Tile;
+  use crate::config::GenerationConfig;
+  use crate::map::Map;
+
+  struct Flood;
+
+  impl GenerationPass for Flood {
+    fn name(&self) -> &str {
+      "flood"
+    }
+
+    fn run(&self, context: &mut GenerationContext) {
+      context.map.tile_map = vec![Tile::Water; context.map.tile_map.len()];
+    }
+  }
+
+  #[test]
+  fn passes_can_be_added_and_removed() {
+    let mut pipeline = Pipeline::default();
+    assert_eq!(
+      pipeline.names(),
+      vec![
+        "terrain",
+        "landmarks",
+        "forests",
+        "settlements",
+        "prefabs",
+        "regions",
+        "wetland",
+        "patches",
+        "resources",
+        "snow",
+        "variants"
+      ]
+    );
+    assert_eq!(pipeline.remove("forests").unwrap().name(), "forests");
+    pipeline.insert_after("terrain", Flood).unwrap();
+    assert_eq!(pipeline.names()[..3], ["terrain", "flood", "landmarks"]);
+    assert!(pipeline.insert_before("rivers", Flood).is_err());
+    assert!(pipeline.remove("forests").is_err());
+
+    let mut map = Map::new(16, 16);
+    pipeline.run(&mut map, 1, &GenerationConfig::default());
+    assert!(map.tile_map.iter().all(|&tile| tile == Tile::Water));
+    assert!(map.tile_map_2.iter().all(|&tile| tile == Tile::Empty));
+  }
+}
@@ -0,0 +1,150 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::f64::consts::PI;
+
+// Candidates tried around a sample before it stops spawning new ones
+const ATTEMPTS: usize = 30;
+
+/// Evenly spaced cells at least `min_distance` apart, using Bridson's
+/// Poisson-disk sampling. Only cells `accept` returns true for are sampled.
+/// When no more samples fit around the existing ones, sampling restarts from
+/// a random cell, so every separate area of accepted cells is reached.
+pub fn poisson_disk<T>(
+  row_count: usize,
+  col_count: usize,
+  min_distance: f64,
+  max_count: usize,
+  rng: &mut StdRng,
+  accept: T,
+) -> Vec<(usize, usize)>
+where
+  T: Fn(usize, usize) -> bool,
+{
+  let mut samples: Vec<(usize, usize)> = Vec::new();
+  if row_count == 0 || col_count == 0 {
+    return samples;
+  }
+  let min_distance = min_distance.max(1.0);
+
+  // Grid cells are small enough to hold at most one sample, so only the
+  // grid cells within two steps need checking
+  let size = min_distance / 2f64.sqrt();
+  let grid_rows = (row_count as f64 / size).ceil() as usize;
+  let grid_cols = (col_count as f64 / size).ceil() as usize;
+  let mut grid: Vec<Option<usize>> = vec![None; grid_rows * grid_cols];
+  let grid_cell = |row: usize, col: usize| ((row as f64 / size) as usize, (col as f64 / size) as usize);
+  let fits = |samples: &[(usize, usize)], grid: &[Option<usize>], row: usize, col: usize| {
+    let (g_row, g_col) = grid_cell(row, col);
+    for r in g_row.saturating_sub(2)..(g_row + 3).min(grid_rows) {
+      for c in g_col.saturating_sub(2)..(g_col + 3).min(grid_cols) {
+        if let Some(i) = grid[r * grid_cols + c] {
+          let (s_row, s_col) = samples[i];
+          let d_row = s_row as f64 - row as f64;
+          let d_col = s_col as f64 - col as f64;
+          if (d_row * d_row + d_col * d_col).sqrt() < min_distance {
+            return false;
+          }
+        }
+      }
+    }
+    true
+  };
+
+  // Cells to restart from, each tried at most once
+  let mut starts = (0..row_count * col_count).collect::<Vec<usize>>();
+  starts.shuffle(rng);
+  let mut starts = starts.into_iter();
+  // Samples that may still have room around them
+  let mut active: Vec<usize> = Vec::new();
+
+  while samples.len() < max_count {
+    let next = if active.is_empty() {
+      let start = starts.find(|&cell| {
+        let (row, col) = (cell / col_count, cell % col_count);
+        fits(&samples, &grid, row, col) && accept(row, col)
+      });
+      match start {
+        Some(cell) => (cell / col_count, cell % col_count),
+        None => break,
+      }
+    } else {
+      let index = rng.gen_range(0..active.len());
+      let (row, col) = samples[active[index]];
+      // Try cells between one and two times the distance away
+      let candidate = (0..ATTEMPTS).find_map(|_| {
+        let angle = rng.gen_range(0.0..2.0 * PI);
+        let distance = rng.gen_range(min_distance..2.0 * min_distance);
+        let c_row = (row as f64 + angle.sin() * distance).round();
+        let c_col = (col as f64 + angle.cos() * distance).round();
+        if c_row < 0.0 || c_col < 0.0 || c_row >= row_count as f64 || c_col >= col_count as f64 {
+          return None;
+        }
+        let (c_row, c_col) = (c_row as usize, c_col as usize);
+        if fits(&samples, &grid, c_row, c_col) && accept(c_row, c_col) {
+          Some((c_row, c_col))
+        } else {
+          None
+        }
+      });
+      match candidate {
+        Some(cell) => cell,
+        None => {
+          active.swap_remove(index);
+          continue;
+        }
+      }
+    };
+
+    let (g_row, g_col) = grid_cell(next.0, next.1);
+    grid[g_row * grid_cols + g_col] = Some(samples.len());
+    active.push(samples.len());
+    samples.push(next);
+  }
+  samples
+}
+
+#[cfg(test)]
+mod test {
+  use super::poisson_disk;
+  use rand::rngs::StdRng;
+  use rand::SeedableRng;
+
+  fn distance(a: (usize, usize), b: (usize, usize)) -> f64 {
+    let d_row = a.0 as f64 - b.0 as f64;
+    let d_col = a.1 as f64 - b.1 as f64;
+    (d_row * d_row + d_col * d_col).sqrt()
+  }
+
+  #[test]
+  fn samples_are_spaced() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let samples = poisson_disk(64, 64, 5.0, usize::MAX, &mut rng, |_, _| true);
+    for (i, &a) in samples.iter().enumerate() {
+      for &b in samples[i + 1..].iter() {
+        assert!(distance(a, b) >= 5.0, "{:?} {:?}", a, b);
+      }
+    }
+    // Spread over the whole map, a grid of 5 x 5 cells would hold 169
+    assert!(samples.len() > 100);
+  }
+
+  #[test]
+  fn samples_are_accepted_cells() {
+    let mut rng = StdRng::seed_from_u64(1);
+    // Two separate strips
+    let accept = |_, col: usize| !(8..56).contains(&col);
+    let samples = poisson_disk(64, 64, 4.0, usize::MAX, &mut rng, accept);
+    assert!(samples.iter().all(|&(row, col)| accept(row, col)));
+    assert!(samples.iter().any(|&(_, col)| col < 8));
+    assert!(samples.iter().any(|&(_, col)| col >= 56));
+  }
+
+  #[test]
+  fn sampling_is_deterministic() {
+    let a = poisson_disk(32, 32, 3.0, 20, &mut StdRng::seed_from_u64(7), |_, _| true);
+    let b = poisson_disk(32, 32, 3.0, 20, &mut StdRng::seed_from_u64(7), |_, _| true);
+    assert_eq!(a, b);
+    assert_eq!(a.len(), 20);
+  }
+}
@@ -0,0 +1,383 @@
+use crate::atlas::Tile;
+use crate::map::Map;
+use crate::poisson::poisson_disk;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+const PREFAB_DATA: &str = include_str!("prefabs.txt");
+
+// Tiles the prefab data can name
+const TILE_NAMES: [(&str, Tile); 13] = [
+  ("Base", Tile::Base),
+  ("Marsh", Tile::Marsh),
+  ("Rock", Tile::Rock),
+  ("Sand", Tile::Sand),
+  ("Tree", Tile::Tree),
+  ("TreeAlt", Tile::TreeAlt),
+  ("Rocks", Tile::Rocks),
+  ("GrassPatch", Tile::GrassPatch),
+  ("Ruin", Tile::Ruin),
+  ("RuinPillar", Tile::RuinPillar),
+  ("Tent", Tile::Tent),
+  ("Campfire", Tile::Campfire),
+  ("Shrine", Tile::Shrine),
+];
+
+fn tile_by_name(name: &str) -> Option<Tile> {
+  TILE_NAMES.iter().find(|&&(n, _)| n == name).map(|&(_, tile)| tile)
+}
+
+/// A hand-made map fragment, see `prefabs.txt` for the format
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Prefab {
+  pub name: String,
+  pub row_count: usize,
+  pub col_count: usize,
+  // Cell of the grids that goes on the chosen site
+  pub anchor: (usize, usize),
+  pub count: usize,
+  pub spacing: f64,
+  // Flat terrain the prefab and the cells around it must stand on
+  pub terrain: Vec<Tile>,
+  // Lowest and highest level of the site above the lowest flat ground
+  pub heights: (u8, u8),
+  // First layer tiles, `None` keeps the tile that is there
+  pub tiles: Vec<Option<Tile>>,
+  pub tiles_2: Vec<Tile>,
+  // Levels each cell is raised by
+  pub raise: Vec<u8>,
+}
+
+impl Prefab {
+  fn new(name: &str) -> Prefab {
+    Prefab {
+      name: name.to_string(),
+      row_count: 0,
+      col_count: 0,
+      anchor: (0, 0),
+      count: 1,
+      spacing: 16.0,
+      terrain: vec![Tile::Base],
+      heights: (0, u8::MAX),
+      tiles: Vec::new(),
+      tiles_2: Vec::new(),
+      raise: Vec::new(),
+    }
+  }
+}
+
+/// Read prefabs from the text format of `prefabs.txt`
+pub(crate) fn parse_prefabs(data: &str) -> Result<Vec<Prefab>, String> {
+  let mut legend: Vec<(char, Tile)> = Vec::new();
+  let mut prefabs: Vec<Prefab> = Vec::new();
+  let mut lines = data
+    .lines()
+    .enumerate()
+    .map(|(i, line)| (i + 1, line.trim()))
+    .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+  while let Some((number, line)) = lines.next() {
+    let error = |message: &str| format!("Line {}: {}", number, message);
+    let mut words = line.split_whitespace();
+    let key = words.next().unwrap_or_default();
+    let values = words.collect::<Vec<&str>>();
+    let numbers = |count: usize| {
+      values
+        .iter()
+        .map(|value| value.parse::<usize>())
+        .collect::<Result<Vec<usize>, _>>()
+        .ok()
+        .filter(|numbers| numbers.len() == count)
+        .ok_or_else(|| error(&format!("{} takes {} numbers", key, count)))
+    };
+
+    if key == "legend" {
+      let symbol = match values.first().map(|value| value.chars().collect::<Vec<char>>()) {
+        Some(chars) if chars.len() == 1 && chars[0] != '.' && chars[0] != '#' => chars[0],
+        _ => return Err(error("Legend needs a single character other than . and #")),
+      };
+      let tile = values
+        .get(1)
+        .and_then(|name| tile_by_name(name))
+        .ok_or_else(|| error("Unknown tile"))?;
+      legend.push((symbol, tile));
+      continue;
+    }
+    if key == "prefab" {
+      let name = values.first().ok_or_else(|| error("Prefab needs a name"))?;
+      prefabs.push(Prefab::new(name));
+      continue;
+    }
+
+    let prefab = prefabs.last_mut().ok_or_else(|| error("Setting outside of a prefab"))?;
+    match key {
+      "size" => {
+        let size = numbers(2)?;
+        prefab.row_count = size[0];
+        prefab.col_count = size[1];
+      }
+      "anchor" => {
+        let anchor = numbers(2)?;
+        prefab.anchor = (anchor[0], anchor[1]);
+      }
+      "count" => prefab.count = numbers(1)?[0],
+      "spacing" => prefab.spacing = numbers(1)?[0] as f64,
+      "terrain" => {
+        prefab.terrain = values
+          .iter()
+          .map(|name| tile_by_name(name))
+          .collect::<Option<Vec<Tile>>>()
+          .ok_or_else(|| error("Unknown tile"))?;
+      }
+      "heights" => {
+        let heights = values
+          .iter()
+          .map(|value| value.parse::<u8>())
+          .collect::<Result<Vec<u8>, _>>()
+          .ok()
+          .filter(|heights| heights.len() == 2)
+          .ok_or_else(|| error("heights takes 2 levels from 0 to 255"))?;
+        prefab.heights = (heights[0], heights[1]);
+      }
+      "tiles" | "objects" | "raise" => {
+        if prefab.row_count == 0 || prefab.col_count == 0 {
+          return Err(error("Grid before the size"));
+        }
+        let mut cells = Vec::new();
+        for _ in 0..prefab.row_count {
+          let (number, row) = lines.next().ok_or_else(|| error("Grid is missing rows"))?;
+          if row.chars().count() != prefab.col_count {
+            return Err(format!("Line {}: Grid row should be {} long", number, prefab.col_count));
+          }
+          cells.extend(row.chars().map(|symbol| (number, symbol)));
+        }
+        let symbol_tile = |number: usize, symbol: char| {
+          legend
+            .iter()
+            .find(|&&(s, _)| s == symbol)
+            .map(|&(_, tile)| tile)
+            .ok_or(format!("Line {}: {} is not in the legend", number, symbol))
+        };
+        match key {
+          "tiles" => {
+            prefab.tiles = cells
+              .into_iter()
+              .map(|(number, symbol)| match symbol {
+                '.' => Ok(None),
+                _ => symbol_tile(number, symbol).map(Some),
+              })
+              .collect::<Result<Vec<Option<Tile>>, String>>()?;
+          }
+          "objects" => {
+            prefab.tiles_2 = cells
+              .into_iter()
+              .map(|(number, symbol)| match symbol {
+                '.' => Ok(Tile::Empty),
+                _ => symbol_tile(number, symbol),
+              })
+              .collect::<Result<Vec<Tile>, String>>()?;
+          }
+          _ => {
+            prefab.raise = cells
+              .into_iter()
+              .map(|(number, symbol)| {
+                symbol
+                  .to_digit(10)
+                  .map(|digit| digit as u8)
+                  .ok_or(format!("Line {}: Raise should be a digit", number))
+              })
+              .collect::<Result<Vec<u8>, String>>()?;
+          }
+        }
+      }
+      _ => return Err(error(&format!("Unknown setting {}", key))),
+    }
+  }
+
+  // Grids left out keep the ground as it is
+  for prefab in prefabs.iter_mut() {
+    let size = prefab.row_count * prefab.col_count;
+    if size == 0 || prefab.anchor.0 >= prefab.row_count || prefab.anchor.1 >= prefab.col_count {
+      return Err(format!("Prefab {} needs a size with the anchor inside it", prefab.name));
+    }
+    prefab.tiles.resize(size, None);
+    prefab.tiles_2.resize(size, Tile::Empty);
+    prefab.raise.resize(size, 0);
+    // Slopes only step one level, so the raised ground, and the ground around
+    // the prefab, can't be more than a level apart
+    let raise = |row: isize, col: isize| {
+      let inside = row >= 0 && col >= 0 && (row as usize) < prefab.row_count && (col as usize) < prefab.col_count;
+      if inside {
+        prefab.raise[row as usize * prefab.col_count + col as usize]
+      } else {
+        0
+      }
+    };
+    for i in 0..size {
+      let (row, col) = ((i / prefab.col_count) as isize, (i % prefab.col_count) as isize);
+      let is_steep = (-1..=1)
+        .flat_map(|d_row| (-1..=1).map(move |d_col| (row + d_row, col + d_col)))
+        .any(|(r, c)| raise(r, c).abs_diff(raise(row, col)) > 1);
+      if is_steep {
+        return Err(format!("Prefab {} raises neighbouring cells more than a level apart", prefab.name));
+      }
+    }
+  }
+  Ok(prefabs)
+}
+
+impl Map {
+  // North west corner of a prefab placed on a site, if it fits on the map
+  // with a cell to spare all around
+  fn prefab_corner(&self, prefab: &Prefab, row: usize, col: usize) -> Option<(usize, usize)> {
+    if row <= prefab.anchor.0
+      || col <= prefab.anchor.1
+      || row - prefab.anchor.0 + prefab.row_count >= self.row_count
+      || col - prefab.anchor.1 + prefab.col_count >= self.col_count
+    {
+      return None;
+    }
+    Some((row - prefab.anchor.0, col - prefab.anchor.1))
+  }
+
+  // The prefab and the cells around it are flat ground of its terrain at one
+  // height, with nothing but trees on it
+  fn can_place_prefab(&self, prefab: &Prefab, row: usize, col: usize) -> bool {
+    let (top, left) = match self.prefab_corner(prefab, row, col) {
+      Some(corner) => corner,
+      None => return false,
+    };
+    let height = self.get_height(row, col);
+    // Heights count from the lowest flat ground, so they fit any number of
+    // height levels
+    let lowest = self.config.tile_heights(Tile::Base).first().cloned().unwrap_or(1);
+    if height < lowest.saturating_add(prefab.heights.0) || height > lowest.saturating_add(prefab.heights.1) {
+      return false;
+    }
+    (top - 1..=top + prefab.row_count).all(|r| {
+      (left - 1..=left + prefab.col_count).all(|c| {
+        prefab.terrain.contains(&self.get_tile(r, c))
+          && self.get_height(r, c) == height
+          && matches!(self.get_tile_2(r, c), Tile::Empty | Tile::Tree | Tile::TreeAlt)
+      })
+    })
+  }
+
+  // Stamp the prefab's layers and heights, then re-tile it and the cells
+  // around it so its edges blend into the ground
+  fn stamp_prefab(&mut self, prefab: &Prefab, row: usize, col: usize) {
+    let (top, left) = match self.prefab_corner(prefab, row, col) {
+      Some(corner) => corner,
+      None => return,
+    };
+    let height = self.get_height(row, col);
+    for p_row in 0..prefab.row_count {
+      for p_col in 0..prefab.col_count {
+        let i = p_row * prefab.col_count + p_col;
+        let (r, c) = (top + p_row, left + p_col);
+        if let Some(tile) = prefab.tiles[i] {
+          self.set_tile(r, c, tile);
+        }
+        self.set_tile_2(r, c, prefab.tiles_2[i]);
+        self.set_height(r, c, height + prefab.raise[i]);
+      }
+    }
+    for r in top - 1..=top + prefab.row_count {
+      for c in left - 1..=left + prefab.col_count {
+        let tile = self.get_tile(r, c).terrain();
+        self.set_tile(r, c, tile);
+        self.terraform_cell(r, c);
+      }
+    }
+  }
+
+  // Stamp the prefabs from `prefabs.txt` on sites that fit them, each kind
+  // spread out by its spacing
+  pub(crate) fn place_prefabs(&mut self, seed: u64) {
+    let prefabs = parse_prefabs(PREFAB_DATA).expect("Invalid prefab data");
+    let mut rng = StdRng::seed_from_u64(seed);
+    for prefab in prefabs.iter() {
+      let sites = poisson_disk(
+        self.row_count,
+        self.col_count,
+        prefab.spacing,
+        prefab.count,
+        &mut rng,
+        |row, col| self.can_place_prefab(prefab, row, col),
+      );
+      for (row, col) in sites {
+        // Copies placed earlier may overlap the site
+        if self.can_place_prefab(prefab, row, col) {
+          self.stamp_prefab(prefab, row, col);
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{parse_prefabs, PREFAB_DATA};
+  use crate::atlas::Tile;
+  use crate::map::Map;
+
+  #[test]
+  fn prefab_data_is_valid() {
+    let prefabs = parse_prefabs(PREFAB_DATA).unwrap();
+    let names = prefabs.iter().map(|prefab| prefab.name.as_str()).collect::<Vec<&str>>();
+    assert_eq!(names, vec!["ruin", "camp", "shrine"]);
+  }
+
+  #[test]
+  fn rejects_bad_prefabs() {
+    assert!(parse_prefabs("legend X Castle").is_err());
+    assert!(parse_prefabs("size 2 2").is_err());
+    assert!(parse_prefabs("prefab a\nsize 2 2\nobjects\n..\n...").is_err());
+    assert!(parse_prefabs("prefab a\nsize 2 2\nobjects\n.X\n..").is_err());
+    assert!(parse_prefabs("prefab a\nsize 2 2\nanchor 2 0").is_err());
+    assert!(parse_prefabs("prefab a\nsize 2 2\nheights 0 256").is_err());
+    assert!(parse_prefabs("prefab a\nsize 3 3\nraise\n000\n020\n000").is_err());
+    assert!(parse_prefabs("prefab a\nsize 3 3\nraise\n000\n010\n000").is_ok());
+    assert!(parse_prefabs("prefab a\nsize 3 3\nraise\n111\n121\n111").is_ok());
+    // Next to the ground around the prefab
+    assert!(parse_prefabs("prefab a\nsize 1 3\nraise\n121").is_err());
+  }
+
+  #[test]
+  fn stamps_and_blends_prefabs() {
+    let prefabs = parse_prefabs(PREFAB_DATA).unwrap();
+    let shrine = prefabs.iter().find(|prefab| prefab.name == "shrine").unwrap();
+    let mut map = Map::new(16, 16);
+    map.height_map = vec![2; 16 * 16];
+    map.set_tile_2(8, 8, Tile::Tree);
+    // Too close to the edge
+    assert!(!map.can_place_prefab(shrine, 2, 8));
+    assert!(map.can_place_prefab(shrine, 8, 8));
+    map.stamp_prefab(shrine, 8, 8);
+
+    assert_eq!(map.get_tile_2(8, 8), Tile::Shrine);
+    assert_eq!(map.get_height(8, 8), 3);
+    assert_eq!(map.get_height(6, 8), 2);
+    // The raised ground slopes down to the ground around it
+    assert_eq!(map.get_tile(7, 8), Tile::BaseNorth);
+    assert_eq!(map.get_tile(8, 9), Tile::BaseEast);
+    assert_eq!(map.validate(), vec![]);
+    // Nothing fits on top of it
+    assert!(!map.can_place_prefab(shrine, 9, 9));
+  }
+
+  #[test]
+  fn prefab_heights_follow_the_levels() {
+    let prefabs = parse_prefabs(PREFAB_DATA).unwrap();
+    let camp = prefabs.iter().find(|prefab| prefab.name == "camp").unwrap();
+    let mut map = Map::new(16, 16);
+    map.config.height_levels = 10;
+    let lowest = map.config.tile_heights(Tile::Base)[0];
+    assert!(lowest > 3);
+    map.height_map = vec![lowest + 1; 16 * 16];
+    assert!(map.can_place_prefab(camp, 8, 8));
+    // Above the levels the camp was made for
+    map.height_map = vec![lowest + 2; 16 * 16];
+    assert!(!map.can_place_prefab(camp, 8, 8));
+  }
+}
@@ -0,0 +1,257 @@
+use crate::atlas::Tile;
+use crate::map::Map;
+use crate::poisson::poisson_disk;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap};
+
+// Extra cost of growing a region up or down a height level, and across rock.
+// Regions spread easily over flat land, so ridges and steep slopes tend to
+// become their borders.
+const CLIMB_COST: usize = 4;
+const MOUNTAIN_COST: usize = 8;
+
+/// An area of land grown from a center, bounded by water
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Region {
+  // Matches the values in `Map::region_map`, starting from 1
+  pub id: u16,
+  pub center: (usize, usize),
+  // Number of cells in the region
+  pub area: usize,
+  // Terrain covering the most cells of the region
+  pub terrain: Tile,
+  // Ids of the regions sharing a border with this one, in ascending order
+  pub neighbors: Vec<u16>,
+}
+
+impl Map {
+  /// Id of the region a cell belongs to, 0 for water
+  pub fn get_region(&self, row: usize, col: usize) -> u16 {
+    self.region_map[row * self.col_count + col]
+  }
+
+  pub fn region_map_ptr(&self) -> *const u16 {
+    self.region_map.as_ptr()
+  }
+
+  /// Regions in order of their ids
+  pub fn regions(&self) -> &[Region] {
+    &self.regions
+  }
+
+  fn is_region_cell(&self, i: usize) -> bool {
+    !matches!(self.tile_map[i].terrain(), Tile::Water | Tile::WaterDeep)
+  }
+
+  // Cost of a region growing from one cell into its neighbor
+  fn growth_cost(&self, from: usize, to: usize) -> usize {
+    let climb = self.height_map[from].abs_diff(self.height_map[to]) as usize;
+    let mountain = matches!(self.tile_map[to].terrain(), Tile::Rock | Tile::Cliff);
+    1 + climb * CLIMB_COST + if mountain { MOUNTAIN_COST } else { 0 }
+  }
+
+  // Split the land into regions grown from evenly spaced centers, each cell
+  // joining the center that is cheapest to reach. Land no center can reach,
+  // like a small island, gets regions of its own.
+  pub(crate) fn partition_regions(&mut self, seed: u64) {
+    let size = self.row_count * self.col_count;
+    self.region_map = vec![0; size];
+    self.regions = Vec::new();
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut centers = poisson_disk(
+      self.row_count,
+      self.col_count,
+      self.config.region_spacing,
+      u16::MAX as usize,
+      &mut rng,
+      |row, col| self.is_region_cell(row * self.col_count + col),
+    );
+    // Centers are taken from the back
+    centers.reverse();
+
+    let mut costs = vec![usize::MAX; size];
+    // Ordered by cost, then by the order cells were reached so ties are
+    // deterministic
+    let mut queue = BinaryHeap::new();
+    let mut order = 0;
+    let mut next = 0;
+    loop {
+      let center = match centers.pop() {
+        Some((row, col)) => row * self.col_count + col,
+        None => {
+          // Every center has been grown, start from land they didn't reach
+          while next < size && (self.region_map[next] != 0 || !self.is_region_cell(next)) {
+            next += 1;
+          }
+          if next == size || self.regions.len() >= u16::MAX as usize {
+            break;
+          }
+          next
+        }
+      };
+      if self.region_map[center] != 0 {
+        continue;
+      }
+      let id = self.regions.len() as u16 + 1;
+      self.regions.push(Region {
+        id,
+        center: (center / self.col_count, center % self.col_count),
+        area: 0,
+        terrain: Tile::Base,
+        neighbors: Vec::new(),
+      });
+      self.region_map[center] = id;
+      costs[center] = 0;
+      queue.push(Reverse((0, order, center)));
+      order += 1;
+
+      // Grow the centers found so far before taking land they haven't
+      // reached
+      if !centers.is_empty() {
+        continue;
+      }
+      while let Some(Reverse((cost, _, i))) = queue.pop() {
+        if cost > costs[i] {
+          continue;
+        }
+        for (row, col) in self.neighbors_4(i / self.col_count, i % self.col_count) {
+          let n = row * self.col_count + col;
+          if !self.is_region_cell(n) {
+            continue;
+          }
+          let cost = cost + self.growth_cost(i, n);
+          if cost < costs[n] {
+            costs[n] = cost;
+            self.region_map[n] = self.region_map[i];
+            queue.push(Reverse((cost, order, n)));
+            order += 1;
+          }
+        }
+      }
+    }
+    self.describe_regions();
+  }
+
+  // Area, main terrain and neighbors of every region
+  fn describe_regions(&mut self) {
+    let mut terrain_counts: Vec<Vec<(Tile, usize)>> = vec![Vec::new(); self.regions.len()];
+    let mut neighbors = vec![BTreeSet::new(); self.regions.len()];
+    for row in 0..self.row_count {
+      for col in 0..self.col_count {
+        let id = self.get_region(row, col);
+        if id == 0 {
+          continue;
+        }
+        let counts = &mut terrain_counts[id as usize - 1];
+        let terrain = self.get_tile(row, col).terrain();
+        match counts.iter_mut().find(|(tile, _)| *tile == terrain) {
+          Some((_, count)) => *count += 1,
+          None => counts.push((terrain, 1)),
+        }
+        // Each border is found from its north or west side
+        for (r, c) in [(row + 1, col), (row, col + 1)] {
+          if r < self.row_count && c < self.col_count {
+            let other = self.get_region(r, c);
+            if other != 0 && other != id {
+              neighbors[id as usize - 1].insert(other);
+              neighbors[other as usize - 1].insert(id);
+            }
+          }
+        }
+      }
+    }
+    for (region, (counts, neighbors)) in self.regions.iter_mut().zip(terrain_counts.into_iter().zip(neighbors)) {
+      region.area = counts.iter().map(|&(_, count)| count).sum();
+      // Ties go to the terrain seen first
+      region.terrain = counts
+        .iter()
+        .fold(None, |best: Option<(Tile, usize)>, &(tile, count)| match best {
+          Some((_, most)) if most >= count => best,
+          _ => Some((tile, count)),
+        })
+        .map_or(Tile::Base, |(tile, _)| tile);
+      region.neighbors = neighbors.into_iter().collect();
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::atlas::Tile;
+  use crate::config::GenerationConfig;
+  use crate::map::Map;
+
+  // Land split by a river down the middle
+  fn river_map() -> Map {
+    let mut map = Map::new(16, 16);
+    map.height_map = vec![2; 16 * 16];
+    for row in 0..16 {
+      map.set_tile(row, 8, Tile::Water);
+      map.set_height(row, 8, 1);
+    }
+    map.config.region_spacing = 6.0;
+    map
+  }
+
+  #[test]
+  fn regions_cover_the_land() {
+    let mut map = river_map();
+    map.partition_regions(1);
+    assert!(map.regions.len() > 2);
+    for row in 0..16 {
+      assert_eq!(map.get_region(row, 8), 0);
+      for col in (0..8).chain(9..16) {
+        assert_ne!(map.get_region(row, col), 0);
+      }
+    }
+    let area: usize = map.regions.iter().map(|region| region.area).sum();
+    assert_eq!(area, 16 * 15);
+    assert!(map.regions.iter().all(|region| region.terrain == Tile::Base));
+  }
+
+  #[test]
+  fn regions_stop_at_water() {
+    let mut map = river_map();
+    map.partition_regions(1);
+    let sides = |cols: std::ops::Range<usize>| {
+      (0..16)
+        .flat_map(|row| cols.clone().map(move |col| (row, col)))
+        .map(|(row, col)| map.get_region(row, col))
+        .collect::<Vec<u16>>()
+    };
+    let (west, east) = (sides(0..8), sides(9..16));
+    assert!(west.iter().all(|id| !east.contains(id)));
+    for region in map.regions.iter() {
+      let is_west = west.contains(&region.id);
+      assert!(region
+        .neighbors
+        .iter()
+        .all(|&id| west.contains(&id) == is_west && map.regions[id as usize - 1].neighbors.contains(&region.id)));
+    }
+  }
+
+  #[test]
+  fn unreached_land_gets_its_own_region() {
+    let mut map = river_map();
+    // Centers too far apart for more than one on the map
+    map.config.region_spacing = 64.0;
+    map.partition_regions(1);
+    assert_eq!(map.regions.len(), 2);
+    assert_ne!(map.get_region(0, 0), map.get_region(0, 15));
+    assert!(map.regions.iter().all(|region| region.area == 16 * 7 || region.area == 16 * 8));
+  }
+
+  #[test]
+  fn generated_maps_have_regions() {
+    let mut map = Map::new(64, 64);
+    map.generate(3, &GenerationConfig::default());
+    assert!(!map.regions.is_empty());
+    for (i, &id) in map.region_map.iter().enumerate() {
+      let is_water = matches!(map.tile_map[i].terrain(), Tile::Water | Tile::WaterDeep);
+      assert_eq!(id == 0, is_water);
+    }
+  }
+}
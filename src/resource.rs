@@ -0,0 +1,168 @@
+use crate::atlas::Tile;
+use crate::map::Map;
+use crate::sampler::WorldSampler;
+use crate::utils::derive_seed;
+use wasm_bindgen::prelude::*;
+
+// Most of a resource one cell can hold
+const MAX_AMOUNT: u8 = 100;
+
+/// Something that can be harvested from a cell
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Resource {
+  Empty = 0,
+  // In rock
+  Ore = 1,
+  // In marsh
+  Clay = 2,
+  // In open and deep water
+  Fish = 3,
+  // From trees
+  Timber = 4,
+}
+
+impl Resource {
+  // Name of the noise stream deposits follow
+  fn stream(self) -> &'static str {
+    match self {
+      Resource::Empty => "",
+      Resource::Ore => "ore",
+      Resource::Clay => "clay",
+      Resource::Fish => "fish",
+      Resource::Timber => "timber",
+    }
+  }
+}
+
+impl Map {
+  /// Resource in a cell and how much of it there is, 0 if there is none
+  pub fn get_resource(&self, row: usize, col: usize) -> (Resource, u8) {
+    let i = row * self.col_count + col;
+    (self.resource_map[i], self.resource_amounts[i])
+  }
+
+  pub fn resource_map_ptr(&self) -> *const Resource {
+    self.resource_map.as_ptr()
+  }
+
+  pub fn resource_amounts_ptr(&self) -> *const u8 {
+    self.resource_amounts.as_ptr()
+  }
+
+  // The resource a cell could hold, whether or not a deposit is there
+  fn resource_for(&self, row: usize, col: usize) -> Resource {
+    let tile = self.get_tile(row, col);
+    if matches!(self.get_tile_2(row, col), Tile::Tree | Tile::TreeAlt) {
+      return Resource::Timber;
+    }
+    if tile.is_road() {
+      return Resource::Empty;
+    }
+    match tile.terrain() {
+      Tile::Rock => Resource::Ore,
+      Tile::Marsh => Resource::Clay,
+      Tile::Water | Tile::WaterDeep => Resource::Fish,
+      _ => Resource::Empty,
+    }
+  }
+
+  // Deposits of ore, clay and fish where their noise, from 0.0 to 1.0, is
+  // within the resource density of the top, richer the further in they are.
+  // This is a threshold on the noise rather than a share of the cells, so
+  // deposits stay in clusters. Every tree has some timber.
+  pub(crate) fn place_resources(&mut self, seed: u64) {
+    let size = self.row_count * self.col_count;
+    self.resource_map = vec![Resource::Empty; size];
+    self.resource_amounts = vec![0; size];
+
+    let density = self.config.resource_density;
+    let samplers = [Resource::Ore, Resource::Clay, Resource::Fish, Resource::Timber].map(|resource| {
+      let sampler = WorldSampler::new(derive_seed(seed, resource.stream()), self.config.world_scale / 2.0);
+      (resource, sampler)
+    });
+    for row in 0..self.row_count {
+      for col in 0..self.col_count {
+        let resource = self.resource_for(row, col);
+        let sampler = match samplers.iter().find(|(r, _)| *r == resource) {
+          Some((_, sampler)) => sampler,
+          None => continue,
+        };
+        let value = sampler.get(row, col);
+        let richness = if resource == Resource::Timber {
+          value
+        } else if density > 0.0 && value >= 1.0 - density {
+          (value - (1.0 - density)) / density
+        } else {
+          continue;
+        };
+        let i = row * self.col_count + col;
+        self.resource_map[i] = resource;
+        self.resource_amounts[i] = 1 + (richness.clamp(0.0, 1.0) * (MAX_AMOUNT - 1) as f64).round() as u8;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Resource;
+  use crate::atlas::Tile;
+  use crate::config::GenerationConfig;
+  use crate::map::Map;
+
+  // Rock, marsh and water in bands, with a tree on the grass between them
+  fn banded_map() -> Map {
+    let mut map = Map::new(16, 16);
+    for col in 0..16 {
+      for row in 0..4 {
+        map.set_tile(row, col, Tile::Rock);
+        map.set_tile(row + 8, col, Tile::Marsh);
+        map.set_tile(row + 12, col, Tile::Water);
+      }
+    }
+    map.set_tile_2(5, 5, Tile::Tree);
+    map
+  }
+
+  #[test]
+  fn resources_follow_the_terrain() {
+    let mut map = banded_map();
+    map.config.resource_density = 1.0;
+    map.place_resources(1);
+    for col in 0..16 {
+      assert_eq!(map.get_resource(0, col).0, Resource::Ore);
+      assert_eq!(map.get_resource(8, col).0, Resource::Clay);
+      assert_eq!(map.get_resource(12, col).0, Resource::Fish);
+      assert_eq!(map.get_resource(6, col), (Resource::Empty, 0));
+    }
+    assert_eq!(map.get_resource(5, 5).0, Resource::Timber);
+    assert!(map.resource_amounts.iter().all(|&amount| amount <= 100));
+    assert!(map
+      .resource_map
+      .iter()
+      .zip(map.resource_amounts.iter())
+      .all(|(&resource, &amount)| (resource == Resource::Empty) == (amount == 0)));
+  }
+
+  #[test]
+  fn density_controls_deposits() {
+    let mut map = banded_map();
+    map.config.resource_density = 0.0;
+    map.place_resources(1);
+    assert!(map
+      .resource_map
+      .iter()
+      .all(|&resource| resource == Resource::Empty || resource == Resource::Timber));
+    assert_ne!(map.get_resource(5, 5).1, 0);
+  }
+
+  #[test]
+  fn generated_maps_have_resources() {
+    let mut map = Map::new(64, 64);
+    map.generate(3, &GenerationConfig::default());
+    let count = |kind: Resource| map.resource_map.iter().filter(|&&resource| resource == kind).count();
+    assert!(count(Resource::Timber) > 0);
+    assert!(count(Resource::Ore) + count(Resource::Clay) + count(Resource::Fish) > 0);
+  }
+}
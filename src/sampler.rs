@@ -0,0 +1,223 @@
+use crate::config::GenerationConfig;
+use crate::utils::derive_seed;
+use noise::{Fbm, MultiFractal, NoiseFn, OpenSimplex, Seedable, SuperSimplex, Worley};
+
+/// Noise function terrain is made from
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NoiseBasis {
+  SuperSimplex = 0,
+  Perlin = 1,
+  OpenSimplex = 2,
+  // Cellular noise, distance to the nearest of a set of random points
+  Worley = 3,
+}
+
+impl NoiseBasis {
+  pub fn from_u8(value: u8) -> Option<NoiseBasis> {
+    match value {
+      0 => Some(NoiseBasis::SuperSimplex),
+      1 => Some(NoiseBasis::Perlin),
+      2 => Some(NoiseBasis::OpenSimplex),
+      3 => Some(NoiseBasis::Worley),
+      _ => None,
+    }
+  }
+}
+
+/// How octaves of noise are combined
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Fractal {
+  // Plain sum of octaves, rolling hills
+  Fbm = 0,
+  // Ridged multifractal, sharp ridges in long mountain ranges
+  Ridged = 1,
+  // Rounded lumps with creases between them
+  Billow = 2,
+}
+
+impl Fractal {
+  pub fn from_u8(value: u8) -> Option<Fractal> {
+    match value {
+      0 => Some(Fractal::Fbm),
+      1 => Some(Fractal::Ridged),
+      2 => Some(Fractal::Billow),
+      _ => None,
+    }
+  }
+}
+
+// Noise seeds are 32-bit
+fn basis_noise(basis: NoiseBasis, seed: u64) -> Box<dyn NoiseFn<[f64; 2]>> {
+  let seed = seed as u32;
+  match basis {
+    NoiseBasis::SuperSimplex => Box::new(SuperSimplex::default().set_seed(seed)),
+    // The crate exports two structs named `Perlin`, so take it from a single
+    // octave of its Perlin fBm
+    NoiseBasis::Perlin => Box::new(Fbm::new().set_octaves(1).set_seed(seed)),
+    NoiseBasis::OpenSimplex => Box::new(OpenSimplex::default().set_seed(seed)),
+    NoiseBasis::Worley => Box::new(Worley::default().set_seed(seed).enable_range(true)),
+  }
+}
+
+/// Samples noise in world space rather than relative to the map size, so
+/// features are the same size in cells whatever the size of the map. The
+/// noise of a small map is a crop of a larger one's, but the finished maps
+/// differ, as erosion and later passes depend on the map size.
+pub struct WorldSampler {
+  noise: Box<dyn NoiseFn<[f64; 2]>>,
+  // Cells per noise feature
+  scale: f64,
+}
+
+impl WorldSampler {
+  pub fn new(seed: u64, scale: f64) -> WorldSampler {
+    WorldSampler::with_basis(NoiseBasis::SuperSimplex, seed, scale)
+  }
+
+  pub fn with_basis(basis: NoiseBasis, seed: u64, scale: f64) -> WorldSampler {
+    WorldSampler {
+      noise: basis_noise(basis, seed),
+      scale,
+    }
+  }
+
+  // Noise value between -1.0 and 1.0 at a point measured in cells
+  fn sample(&self, row: f64, col: f64) -> f64 {
+    self.noise.get([col / self.scale, row / self.scale]).clamp(-1.0, 1.0)
+  }
+
+  /// Noise value between 0.0 and 1.0 at a cell
+  pub fn get(&self, row: usize, col: usize) -> f64 {
+    self.sample(row as f64, col as f64) / 2.0 + 0.5
+  }
+}
+
+/// Terrain elevation made from several octaves of noise
+pub struct TerrainSampler {
+  // Samplers and their amplitudes
+  octaves: Vec<(WorldSampler, f64)>,
+  fractal: Fractal,
+  // Samplers moving the point the octaves are read at, by rows and by
+  // columns, and the most cells they move it
+  warp: Option<(WorldSampler, WorldSampler, f64)>,
+}
+
+impl TerrainSampler {
+  pub fn new(seed: u64, config: &GenerationConfig) -> TerrainSampler {
+    let octaves = (0..config.octaves.max(1))
+      .map(|i| {
+        let seed = derive_seed(seed, &format!("terrain-{}", i + 1));
+        let frequency = config.lacunarity.powi(i as i32);
+        let sampler = WorldSampler::with_basis(config.noise_basis, seed, config.world_scale / frequency);
+        (sampler, config.persistence.powi(i as i32))
+      })
+      .collect();
+    let warp = (config.warp_strength > 0.0).then(|| {
+      (
+        WorldSampler::new(derive_seed(seed, "warp-rows"), config.world_scale),
+        WorldSampler::new(derive_seed(seed, "warp-cols"), config.world_scale),
+        config.warp_strength,
+      )
+    });
+    TerrainSampler {
+      octaves,
+      fractal: config.fractal,
+      warp,
+    }
+  }
+
+  /// Elevation between 0.0 and 1.0 at a cell
+  pub fn elevation(&self, row: usize, col: usize) -> f64 {
+    let (mut row, mut col) = (row as f64, col as f64);
+    if let Some((rows, cols, strength)) = &self.warp {
+      (row, col) = (
+        row + rows.sample(row, col) * strength,
+        col + cols.sample(row, col) * strength,
+      );
+    }
+    // Ridged octaves are weighted by the octaves before them, so detail
+    // only builds up along the ridges
+    let mut weight = 1.0;
+    let (mut sum, mut amplitudes) = (0.0, 0.0);
+    for (sampler, amplitude) in self.octaves.iter() {
+      let value = sampler.sample(row, col);
+      let value = match self.fractal {
+        Fractal::Fbm => value / 2.0 + 0.5,
+        Fractal::Ridged => {
+          let ridge = (1.0 - value.abs()) * weight;
+          weight = (ridge * 2.0).clamp(0.0, 1.0);
+          ridge
+        }
+        Fractal::Billow => value.abs(),
+      };
+      sum += amplitude * value;
+      amplitudes += amplitude;
+    }
+    // Divide by the sum of amplitudes to get a value between 0 and 1
+    sum / amplitudes
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Fractal, NoiseBasis, TerrainSampler};
+  use crate::config::GenerationConfig;
+  use crate::map::Map;
+
+  fn range(terrain: &TerrainSampler) -> (f64, f64) {
+    let values = (0..16 * 16)
+      .map(|i| terrain.elevation(i / 16, i % 16))
+      .collect::<Vec<f64>>();
+    let min = values.iter().cloned().fold(f64::MAX, f64::min);
+    let max = values.iter().cloned().fold(f64::MIN, f64::max);
+    (min, max)
+  }
+
+  #[test]
+  fn small_maps_are_not_flat() {
+    let terrain = TerrainSampler::new(1, &GenerationConfig::default());
+    let (min, max) = range(&terrain);
+    assert!(max - min > 0.05);
+  }
+
+  #[test]
+  fn sampling_is_independent_of_map_size() {
+    // The same cell has the same height however much of the world is used
+    let config = GenerationConfig::default();
+    let small = Map::new(32, 32).noise_heightfield(7, &config);
+    let large = Map::new(64, 64).noise_heightfield(7, &config);
+    for row in 0..32 {
+      assert_eq!(small.values[row * 32..(row + 1) * 32], large.values[row * 64..row * 64 + 32]);
+    }
+  }
+
+  #[test]
+  fn every_noise_stays_in_range() {
+    let bases = [NoiseBasis::SuperSimplex, NoiseBasis::Perlin, NoiseBasis::OpenSimplex, NoiseBasis::Worley];
+    for &noise_basis in bases.iter() {
+      for &fractal in [Fractal::Fbm, Fractal::Ridged, Fractal::Billow].iter() {
+        let config = GenerationConfig {
+          noise_basis,
+          fractal,
+          octaves: 5,
+          warp_strength: 8.0,
+          ..GenerationConfig::default()
+        };
+        let (min, max) = range(&TerrainSampler::new(3, &config));
+        assert!(min >= 0.0 && max <= 1.0, "{:?} {:?}", noise_basis, fractal);
+        assert!(max - min > 0.01, "{:?} {:?}", noise_basis, fractal);
+      }
+    }
+  }
+
+  #[test]
+  fn warping_moves_the_terrain() {
+    let config = GenerationConfig {
+      warp_strength: 16.0,
+      ..GenerationConfig::default()
+    };
+    let plain = TerrainSampler::new(5, &GenerationConfig::default());
+    let warped = TerrainSampler::new(5, &config);
+    assert!((0..16).any(|i| plain.elevation(i, i) != warped.elevation(i, i)));
+  }
+}
@@ -0,0 +1,299 @@
+use crate::atlas::Tile;
+use crate::graph::dijkstra_path_to_target;
+use crate::map::Map;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+
+// Cells around the center that a settlement's buildings can use
+const RADIUS: usize = 3;
+// Sites closer than this to water or a road score higher
+const REACH: usize = 12;
+const MAX_BUILDINGS: usize = 6;
+// Tries at fitting another building into a settlement
+const ATTEMPTS: usize = 40;
+
+// Building tiles as (row, col, tile), relative to the north west corner of
+// the building
+type Footprint = &'static [(usize, usize, Tile)];
+const HOUSE: Footprint = &[(0, 0, Tile::House)];
+const BARN: Footprint = &[(0, 0, Tile::BarnWest), (0, 1, Tile::BarnEast)];
+const HALL: Footprint = &[
+  (0, 0, Tile::HallNorthWest),
+  (0, 1, Tile::HallNorthEast),
+  (1, 0, Tile::HallSouthWest),
+  (1, 1, Tile::HallSouthEast),
+];
+
+/// A village of buildings joined to the road network
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Settlement {
+  // Starting from 1
+  pub id: u16,
+  // North west corner of the hall in the middle
+  pub center: (usize, usize),
+  // Box around the buildings as (top, left, bottom, right), inclusive
+  pub bounds: (usize, usize, usize, usize),
+  pub buildings: usize,
+}
+
+impl Settlement {
+  pub(crate) fn contains(&self, row: usize, col: usize) -> bool {
+    let (top, left, bottom, right) = self.bounds;
+    row >= top && row <= bottom && col >= left && col <= right
+  }
+}
+
+/// The building a building tile is part of, and where in the building it is
+pub(crate) fn footprint(tile: Tile) -> Option<(Footprint, (usize, usize))> {
+  [HOUSE, BARN, HALL].iter().find_map(|&footprint| {
+    footprint
+      .iter()
+      .find(|part| part.2 == tile)
+      .map(|part| (footprint, (part.0, part.1)))
+  })
+}
+
+fn distance(a: (usize, usize), b: (usize, usize)) -> f64 {
+  let d_row = a.0 as f64 - b.0 as f64;
+  let d_col = a.1 as f64 - b.1 as f64;
+  (d_row * d_row + d_col * d_col).sqrt()
+}
+
+impl Map {
+  /// Settlements in the order they were placed
+  pub fn settlements(&self) -> &[Settlement] {
+    &self.settlements
+  }
+
+  // Distance from every cell to the nearest tile matching `is_source`, up to
+  // `REACH`
+  fn distance_field<T>(&self, is_source: T) -> Vec<usize>
+  where
+    T: Fn(Tile) -> bool,
+  {
+    let mut distances = vec![REACH; self.tile_map.len()];
+    let mut queue = VecDeque::new();
+    for (i, &tile) in self.tile_map.iter().enumerate() {
+      if is_source(tile) {
+        distances[i] = 0;
+        queue.push_back(i);
+      }
+    }
+    while let Some(i) = queue.pop_front() {
+      let next = distances[i] + 1;
+      for (row, col) in self.neighbors_4(i / self.col_count, i % self.col_count) {
+        let n = row * self.col_count + col;
+        if next < distances[n] {
+          distances[n] = next;
+          queue.push_back(n);
+        }
+      }
+    }
+    distances
+  }
+
+  // Flat ground at one height all around the center, with nothing but trees
+  // on it
+  fn is_settlement_site(&self, row: usize, col: usize) -> bool {
+    if row < RADIUS || col < RADIUS || row + RADIUS >= self.row_count || col + RADIUS >= self.col_count {
+      return false;
+    }
+    let height = self.get_height(row, col);
+    (row - RADIUS..=row + RADIUS).all(|r| {
+      (col - RADIUS..=col + RADIUS).all(|c| {
+        self.get_tile(r, c) == Tile::Base
+          && self.get_height(r, c) == height
+          && matches!(self.get_tile_2(r, c), Tile::Empty | Tile::Tree | Tile::TreeAlt)
+      })
+    })
+  }
+
+  // The building fits within the settlement, with a gap to other buildings
+  fn can_place_building(&self, center: (usize, usize), corner: (usize, usize), footprint: Footprint) -> bool {
+    footprint.iter().all(|&(d_row, d_col, _)| {
+      let (row, col) = (corner.0 + d_row, corner.1 + d_col);
+      row + RADIUS >= center.0
+        && row <= center.0 + RADIUS
+        && col + RADIUS >= center.1
+        && col <= center.1 + RADIUS
+        && self.get_tile(row, col) == Tile::Base
+        && !self
+          .neighbors_8(row, col)
+          .iter()
+          .chain([(row, col)].iter())
+          .any(|n| self.get_tile_2(n.0, n.1).is_building())
+    })
+  }
+
+  // Place villages on the best sites: flat ground close to water and roads.
+  // Each gets a hall and a few smaller buildings, and a road to the nearest
+  // road or other settlement.
+  pub(crate) fn place_settlements(&mut self, seed: u64) {
+    self.settlements = Vec::new();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let water = self.distance_field(|tile| matches!(tile, Tile::Water | Tile::WaterDeep));
+    let roads = self.distance_field(|tile| tile.is_road());
+    let mut sites = (0..self.tile_map.len())
+      .filter(|&i| self.is_settlement_site(i / self.col_count, i % self.col_count))
+      .map(|i| (2 * REACH - water[i] - roads[i], i))
+      .collect::<Vec<(usize, usize)>>();
+    // Best sites first, ties in reading order
+    sites.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    for (_, i) in sites {
+      if self.settlements.len() >= self.config.settlements {
+        break;
+      }
+      let center = (i / self.col_count, i % self.col_count);
+      let is_spaced = self
+        .settlements
+        .iter()
+        .all(|settlement| distance(settlement.center, center) >= self.config.settlement_spacing);
+      // Roads to earlier settlements may have been built over the site
+      if is_spaced && self.is_settlement_site(center.0, center.1) {
+        self.build_settlement(center, &mut rng);
+      }
+    }
+  }
+
+  fn build_settlement(&mut self, center: (usize, usize), rng: &mut StdRng) {
+    let mut cells = Vec::new();
+    let mut stamp = |map: &mut Map, corner: (usize, usize), footprint: Footprint| {
+      for &(d_row, d_col, tile) in footprint {
+        map.set_tile_2(corner.0 + d_row, corner.1 + d_col, tile);
+        cells.push((corner.0 + d_row, corner.1 + d_col));
+      }
+    };
+
+    // The hall stands in the middle, the site is always big enough for it
+    stamp(self, center, HALL);
+    let mut buildings = 1;
+    for _ in 0..ATTEMPTS {
+      if buildings >= MAX_BUILDINGS {
+        break;
+      }
+      let footprint = if rng.gen_bool(0.7) { HOUSE } else { BARN };
+      let corner = (
+        rng.gen_range(center.0 - RADIUS..=center.0 + RADIUS),
+        rng.gen_range(center.1 - RADIUS..=center.1 + RADIUS),
+      );
+      if self.can_place_building(center, corner, footprint) {
+        stamp(self, corner, footprint);
+        buildings += 1;
+      }
+    }
+
+    let top = cells.iter().map(|c| c.0).min().unwrap_or(center.0);
+    let left = cells.iter().map(|c| c.1).min().unwrap_or(center.1);
+    let bottom = cells.iter().map(|c| c.0).max().unwrap_or(center.0);
+    let right = cells.iter().map(|c| c.1).max().unwrap_or(center.1);
+    // Clear the trees around the buildings
+    for row in top.saturating_sub(1)..=(bottom + 1).min(self.row_count - 1) {
+      for col in left.saturating_sub(1)..=(right + 1).min(self.col_count - 1) {
+        if matches!(self.get_tile_2(row, col), Tile::Tree | Tile::TreeAlt) {
+          self.set_tile_2(row, col, Tile::Empty);
+        }
+      }
+    }
+
+    let settlement = Settlement {
+      id: self.settlements.len() as u16 + 1,
+      center,
+      bounds: (top, left, bottom, right),
+      buildings,
+    };
+    self.connect_settlement(&settlement);
+    self.settlements.push(settlement);
+  }
+
+  // Build a road from the hall to the closest road, or failing that to another
+  // settlement
+  fn connect_settlement(&mut self, settlement: &Settlement) {
+    let start = settlement.center;
+    let is_road_side = |(row, col): (usize, usize)| {
+      self
+        .neighbors_4(row, col)
+        .into_iter()
+        .find(|n| self.get_tile(n.0, n.1).is_road())
+    };
+    // Roads can bridge narrow water and tunnel through ridges, as between
+    // the landmarks
+    let neighbors_for_cell = |(row, col)| {
+      self
+        .road_cells(row, col)
+        .into_iter()
+        .filter(|&((n_row, n_col), _)| {
+          let tile_2 = self.get_tile_2(n_row, n_col);
+          tile_2 != Tile::Rocks && !(tile_2.is_building() && settlement.contains(n_row, n_col))
+        })
+        .collect()
+    };
+    let is_match = |(row, col): (usize, usize)| {
+      (row, col) != start
+        && (is_road_side((row, col)).is_some()
+          || (self.get_tile_2(row, col).is_building() && !settlement.contains(row, col)))
+    };
+
+    if let Some(mut path) = dijkstra_path_to_target(start, neighbors_for_cell, is_match) {
+      // Join the road rather than stopping next to it
+      if let Some(road) = path.last().and_then(|&end| is_road_side(end)) {
+        path.push(road);
+      }
+      self.lay_road(&path);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::atlas::Tile;
+  use crate::map::Map;
+
+  #[test]
+  fn settlements_join_the_roads() {
+    let mut map = Map::new(24, 24);
+    map.height_map = vec![2; 24 * 24];
+    // A road between two landmarks
+    for col in 1..23 {
+      map.set_tile(20, col, Tile::Road2);
+    }
+    map.set_tile_2(20, 0, Tile::Rocks);
+    map.set_tile_2(20, 23, Tile::Rocks);
+    map.config.settlements = 1;
+    map.place_settlements(1);
+    assert_eq!(map.settlements.len(), 1);
+    let settlement = &map.settlements[0];
+    // The best site is as close to the road as it can be
+    assert_eq!(settlement.center, (16, 3));
+    let (top, left, bottom, right) = settlement.bounds;
+    for row in top..=bottom {
+      for col in left..=right {
+        assert!(!matches!(map.get_tile_2(row, col), Tile::Tree | Tile::TreeAlt));
+      }
+    }
+    assert!(settlement.buildings > 1);
+    assert_eq!(map.get_tile_2(16, 3), Tile::HallNorthWest);
+    assert_eq!(map.get_tile_2(17, 4), Tile::HallSouthEast);
+    assert!((0..24).any(|col| map.get_tile(19, col).is_road()));
+    assert_eq!(map.validate(), vec![]);
+  }
+
+  #[test]
+  fn settlements_are_spaced() {
+    let mut map = Map::new(32, 64);
+    map.height_map = vec![2; 32 * 64];
+    map.config.settlements = 10;
+    map.config.settlement_spacing = 20.0;
+    map.place_settlements(1);
+    assert!(map.settlements.len() > 1);
+    for a in map.settlements.iter() {
+      for b in map.settlements.iter().filter(|b| b.id != a.id) {
+        let d_row = a.center.0 as f64 - b.center.0 as f64;
+        let d_col = a.center.1 as f64 - b.center.1 as f64;
+        assert!((d_row * d_row + d_col * d_col).sqrt() >= 20.0);
+      }
+    }
+    assert_eq!(map.validate(), vec![]);
+  }
+}
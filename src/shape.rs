@@ -0,0 +1,150 @@
+use crate::utils::derive_seed;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use wasm_bindgen::prelude::*;
+
+/// Overall shape of the land
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Shape {
+  // Terrain noise only
+  Open = 0,
+  // A single island in the middle of the map
+  Island = 1,
+  // Several smaller islands
+  Archipelago = 2,
+  // Land with coast along one side of the map
+  Continent = 3,
+  // No ocean at all
+  Inland = 4,
+}
+
+impl Shape {
+  pub fn from_u8(value: u8) -> Option<Shape> {
+    match value {
+      0 => Some(Shape::Open),
+      1 => Some(Shape::Island),
+      2 => Some(Shape::Archipelago),
+      3 => Some(Shape::Continent),
+      4 => Some(Shape::Inland),
+      _ => None,
+    }
+  }
+}
+
+/// Falloff mask applied to terrain elevation to give the map its shape
+pub struct ShapeMask {
+  shape: Shape,
+  row_count: usize,
+  col_count: usize,
+  // Islands as (row, col, radius), in coordinates from -1.0 to 1.0
+  islands: Vec<(f64, f64, f64)>,
+  // Side of the map facing the sea: 0 north, 1 east, 2 south, 3 west
+  coast: u8,
+  // Lowest elevation that isn't water
+  land_min: f64,
+}
+
+impl ShapeMask {
+  /// `land_min` is the lowest elevation that will end up as land
+  pub fn new(shape: Shape, row_count: usize, col_count: usize, seed: u64, land_min: f64) -> ShapeMask {
+    let mut rng = StdRng::seed_from_u64(derive_seed(seed, "shape"));
+    let islands = match shape {
+      Shape::Island => vec![(0.0, 0.0, 0.85)],
+      Shape::Archipelago => {
+        let count = rng.gen_range(3..7);
+        (0..count)
+          .map(|_| {
+            (
+              rng.gen_range(-0.7..0.7),
+              rng.gen_range(-0.7..0.7),
+              rng.gen_range(0.2..0.45),
+            )
+          })
+          .collect()
+      }
+      _ => Vec::new(),
+    };
+    ShapeMask {
+      shape,
+      row_count,
+      col_count,
+      islands,
+      coast: rng.gen_range(0..4),
+      land_min,
+    }
+  }
+
+  // Mask value from 0.0 (sea) to 1.0 (land) at a cell
+  fn mask(&self, row: usize, col: usize) -> f64 {
+    // -1.0 to 1.0 across the map
+    let y = (row as f64 + 0.5) / self.row_count as f64 * 2.0 - 1.0;
+    let x = (col as f64 + 0.5) / self.col_count as f64 * 2.0 - 1.0;
+    match self.shape {
+      Shape::Island | Shape::Archipelago => self
+        .islands
+        .iter()
+        .map(|&(i_y, i_x, radius)| {
+          let distance = ((y - i_y).powi(2) + (x - i_x).powi(2)).sqrt() / radius;
+          (1.0 - distance * distance).max(0.0)
+        })
+        .fold(0.0, f64::max),
+      Shape::Continent => {
+        // 0.0 at the coast, 1.0 at the opposite side
+        let inland = match self.coast {
+          0 => y,
+          1 => -x,
+          2 => -y,
+          _ => x,
+        } * 0.5
+          + 0.5;
+        let t = (inland / 0.5).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+      }
+      _ => 1.0,
+    }
+  }
+
+  /// Combine the mask with an elevation between 0.0 and 1.0
+  pub fn apply(&self, elevation: f64, row: usize, col: usize) -> f64 {
+    match self.shape {
+      Shape::Open => elevation,
+      // Squeeze elevations above the water
+      Shape::Inland => self.land_min + (1.0 - self.land_min) * elevation,
+      _ => {
+        // Open sea keeps some relief but stays well under the shoreline
+        let sea = self.land_min * 0.5;
+        let mask = self.mask(row, col);
+        (elevation * (sea + (1.0 - sea) * mask) + 0.15 * mask).min(1.0)
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Shape, ShapeMask};
+
+  #[test]
+  fn island_is_surrounded_by_sea() {
+    let mask = ShapeMask::new(Shape::Island, 32, 32, 1, 0.25);
+    assert!(mask.apply(1.0, 0, 0) < 0.25);
+    assert!(mask.apply(1.0, 31, 16) < 0.25);
+    assert!(mask.apply(0.5, 16, 16) > 0.5);
+  }
+
+  #[test]
+  fn continent_has_one_coast() {
+    let mask = ShapeMask::new(Shape::Continent, 32, 32, 1, 0.25);
+    let edges = [mask.mask(0, 16), mask.mask(16, 31), mask.mask(31, 16), mask.mask(16, 0)];
+    assert_eq!(edges.iter().filter(|&&m| m < 0.01).count(), 1);
+    assert_eq!(edges.iter().filter(|&&m| m > 0.99).count(), 3);
+  }
+
+  #[test]
+  fn inland_has_no_water() {
+    let mask = ShapeMask::new(Shape::Inland, 32, 32, 1, 0.25);
+    assert!(mask.apply(0.0, 0, 0) >= 0.25);
+    assert_eq!(mask.apply(1.0, 0, 0), 1.0);
+  }
+}
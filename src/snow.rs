@@ -0,0 +1,93 @@
+use crate::atlas::Tile;
+use crate::map::Map;
+
+impl Map {
+  // Cover rock and cliffs at or above the snow line in snow and freeze the
+  // water there. Snow tiles keep the shape of the ground under them, so slopes
+  // and edges still match the heights around them.
+  pub(crate) fn cap_snow(&mut self) {
+    let snow_line = self.config.snow_line_height();
+    for row in 0..self.row_count {
+      for col in 0..self.col_count {
+        if self.get_height(row, col) < snow_line {
+          continue;
+        }
+        let tile = self.get_tile(row, col);
+        let snowy = tile.snowy();
+        if snowy == tile {
+          continue;
+        }
+        self.set_tile(row, col, snowy);
+        // Nothing grows on ice
+        if snowy == Tile::Ice && self.get_tile_2(row, col) == Tile::WaterLily {
+          self.set_tile_2(row, col, Tile::Empty);
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::atlas::Tile;
+  use crate::config::GenerationConfig;
+  use crate::map::Map;
+
+  #[test]
+  fn snow_covers_high_rock() {
+    let mut map = Map::new(4, 4);
+    map.height_map = vec![4, 4, 3, 3, 4, 4, 3, 3, 4, 4, 3, 3, 4, 4, 3, 3];
+    map.tile_map = vec![Tile::Rock; 16];
+    map.set_tile(0, 1, Tile::RockEast);
+    map.set_tile(3, 0, Tile::Water);
+    map.set_tile_2(3, 0, Tile::WaterLily);
+    map.set_tile(3, 1, Tile::WaterDeep);
+    map.cap_snow();
+    assert_eq!(map.get_tile(0, 0), Tile::Snow);
+    assert_eq!(map.get_tile(0, 1), Tile::SnowEast);
+    assert_eq!(map.get_tile(0, 2), Tile::Rock);
+    assert_eq!(map.get_tile(3, 0), Tile::Ice);
+    assert_eq!(map.get_tile_2(3, 0), Tile::Empty);
+    // Deep water doesn't freeze
+    assert_eq!(map.get_tile(3, 1), Tile::WaterDeep);
+    assert_eq!(Tile::SnowEast.terrain(), Tile::Rock);
+    assert_eq!(Tile::SnowEast.thawed(), Tile::RockEast);
+
+    // Cliffs get their own snow, and stay cliffs under it
+    map.set_tile(1, 0, Tile::CliffSouth);
+    map.cap_snow();
+    assert_eq!(map.get_tile(1, 0), Tile::SnowCliffSouth);
+    assert_eq!(Tile::SnowCliffSouth.terrain(), Tile::Cliff);
+    assert_eq!(Tile::SnowCliffSouth.thawed(), Tile::CliffSouth);
+  }
+
+  #[test]
+  fn snow_line_is_configurable() {
+    let mut map = Map::new(1, 2);
+    map.height_map = vec![4, 3];
+    map.tile_map = vec![Tile::Rock; 2];
+    map.config.snow_line = 1.5;
+    map.cap_snow();
+    assert_eq!(map.tile_map, vec![Tile::Rock, Tile::Rock]);
+    map.config.snow_line = 0.75;
+    map.cap_snow();
+    assert_eq!(map.tile_map, vec![Tile::Snow, Tile::Snow]);
+  }
+
+  #[test]
+  fn snowy_maps_are_valid() {
+    let config = GenerationConfig {
+      height_levels: 8,
+      snow_line: 0.75,
+      ..GenerationConfig::default()
+    };
+    let mut snowy = false;
+    for seed in 0..20 {
+      let mut map = Map::new(48, 48);
+      map.generate(seed, &config);
+      assert_eq!(map.validate(), vec![], "seed {}", seed);
+      snowy |= map.tile_map.iter().any(|tile| tile.is_snow());
+    }
+    assert!(snowy);
+  }
+}
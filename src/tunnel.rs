@@ -0,0 +1,164 @@
+use crate::atlas::Tile;
+use crate::map::{cells_between, Map, STEPS};
+
+// Cost of each cell a tunnel bores through, and the extra cost of its
+// entrances. Going round a ridge on a grid seldom takes many more cells, so
+// tunnels only pay off if they cost little more than the road.
+const TUNNEL_COST: usize = 1;
+const ENTRANCE_COST: usize = 1;
+
+fn runs_north_south(tile: Tile) -> bool {
+  matches!(tile, Tile::TunnelNorth | Tile::TunnelSouth | Tile::Tunnel1)
+}
+
+impl Map {
+  // The land a tunnel going `step` from a cell would come out on, and how
+  // many cells of rock above the road it bores through
+  fn tunnel_end(&self, row: usize, col: usize, step: (isize, isize)) -> Option<((usize, usize), usize)> {
+    let height = self.get_height(row, col);
+    if self.get_tile(row, col) != Tile::Base {
+      return None;
+    }
+    for length in 1..=self.config.tunnel_length + 1 {
+      let (r, c) = (row as isize + step.0 * length as isize, col as isize + step.1 * length as isize);
+      if !self.in_bounds(r, c) {
+        return None;
+      }
+      let (r, c) = (r as usize, c as usize);
+      if length > 1 && self.get_tile(r, c) == Tile::Base && self.get_height(r, c) == height {
+        return Some(((r, c), length - 1));
+      }
+      if length > self.config.tunnel_length
+        || !matches!(self.get_tile(r, c).terrain(), Tile::Rock | Tile::Cliff)
+        || self.get_height(r, c) <= height
+        || self.get_tile_2(r, c) != Tile::Empty
+      {
+        return None;
+      }
+    }
+    None
+  }
+
+  /// Land a road can reach through a tunnel from a cell, with the cost of
+  /// going there
+  pub(crate) fn tunnel_cells(&self, row: usize, col: usize) -> Vec<((usize, usize), usize)> {
+    STEPS
+      .iter()
+      .filter_map(|&step| self.tunnel_end(row, col, step))
+      .map(|(end, length)| (end, 1 + ENTRANCE_COST + length * TUNNEL_COST))
+      .collect()
+  }
+
+  // Entrances on the rock faces at either end of a tunnel, and hidden tunnel
+  // cells between them
+  pub(crate) fn build_tunnel(&mut self, start: (usize, usize), end: (usize, usize)) {
+    let north_south = start.1 == end.1;
+    let cells = cells_between(start, end);
+    // A single cell has no room for both entrances
+    let has_entrances = cells.len() > 1;
+    for (i, &(row, col)) in cells.iter().enumerate() {
+      let tile = match (north_south, has_entrances && i == 0, has_entrances && i + 1 == cells.len()) {
+        (true, true, _) => Tile::TunnelNorth,
+        (true, _, true) => Tile::TunnelSouth,
+        (true, _, _) => Tile::Tunnel1,
+        (false, true, _) => Tile::TunnelWest,
+        (false, _, true) => Tile::TunnelEast,
+        (false, _, _) => Tile::Tunnel2,
+      };
+      self.set_tile_2(row, col, tile);
+    }
+  }
+
+  /// Whether two neighboring cells are joined by a tunnel: both are tunnel
+  /// cells or ground at an entrance, in a line along the tunnel
+  pub(crate) fn is_tunnel_link(&self, a: (usize, usize), b: (usize, usize)) -> bool {
+    let north_south = a.1 == b.1;
+    let is_end = |(row, col): (usize, usize)| {
+      let tile_2 = self.get_tile_2(row, col);
+      let tile = self.get_tile(row, col);
+      if tile_2.is_tunnel() {
+        runs_north_south(tile_2) == north_south
+      } else {
+        matches!(tile, Tile::Base | Tile::Sand) || tile.is_road()
+      }
+    };
+    (self.get_tile_2(a.0, a.1).is_tunnel() || self.get_tile_2(b.0, b.1).is_tunnel()) && is_end(a) && is_end(b)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::atlas::Tile;
+  use crate::config::GenerationMode;
+  use crate::game::Game;
+  use crate::map::Map;
+  use crate::shape::Shape;
+
+  // Land at height 2 with a rock ridge at height 4 `width` cells wide down
+  // the middle
+  fn ridge_map(width: usize) -> Map {
+    let mut map = Map::new(8, 16);
+    map.height_map = vec![2; 8 * 16];
+    for row in 0..8 {
+      for col in 8 - width / 2..8 - width / 2 + width {
+        map.set_tile(row, col, Tile::Rock);
+        map.set_height(row, col, 4);
+      }
+    }
+    map
+  }
+
+  #[test]
+  fn roads_tunnel_through_ridges() {
+    let map = ridge_map(4);
+    assert_eq!(map.tunnel_cells(3, 5), vec![((3, 10), 6)]);
+    assert!(map.road_cells(3, 5).contains(&((3, 10), 6)));
+
+    // Too long to bore through
+    let map = ridge_map(12);
+    assert_eq!(map.tunnel_cells(3, 1), vec![]);
+  }
+
+  #[test]
+  fn short_tunnels_have_no_entrances() {
+    let mut map = ridge_map(1);
+    map.build_tunnel((3, 7), (3, 9));
+    map.build_tunnel((5, 8), (7, 8));
+    assert_eq!(map.get_tile_2(3, 8), Tile::Tunnel2);
+    assert_eq!(map.get_tile_2(6, 8), Tile::Tunnel1);
+  }
+
+  #[test]
+  fn tunnels_can_be_walked() {
+    let mut map = ridge_map(4);
+    map.set_tile_2(3, 0, Tile::Rocks);
+    map.set_tile_2(3, 15, Tile::Rocks);
+    let path = (0..=5)
+      .map(|col| (3, col))
+      .chain((10..=15).map(|col| (3, col)))
+      .collect::<Vec<(usize, usize)>>();
+    map.lay_road(&path);
+    assert_eq!(map.get_tile(3, 7), Tile::Rock);
+    let tunnel = (6..10).map(|col| map.get_tile_2(3, col)).collect::<Vec<Tile>>();
+    assert_eq!(
+      tunnel,
+      vec![Tile::TunnelWest, Tile::Tunnel2, Tile::Tunnel2, Tile::TunnelEast]
+    );
+    assert!(map.walkable_cells(3, 5).contains(&(3, 6)));
+    assert_eq!(map.walkable_cells(3, 7), vec![(3, 6), (3, 8)]);
+    assert!(map.walkable_cells(3, 9).contains(&(3, 10)));
+    // Not in or out through the sides
+    assert!(!map.walkable_cells(2, 7).contains(&(3, 7)));
+    assert_eq!(map.validate(), vec![]);
+  }
+
+  #[test]
+  fn generated_roads_tunnel_through_ridges() {
+    // Seeds whose roads bore through rock
+    for &(seed, size) in [("13", 64), ("34", 128)].iter() {
+      let game = Game::new(size, size, seed, Shape::Open, GenerationMode::Noise);
+      assert!(game.tile_map_2().iter().any(|tile| tile.is_tunnel()), "No tunnels for seed {}", seed);
+      assert_eq!(game.js_validate(), Vec::<String>::new());
+    }
+  }
+}
@@ -8,3 +8,41 @@ pub fn set_panic_hook() {
     #[cfg(feature = "console_error_panic_hook")]
     console_error_panic_hook::set_once();
 }
+
+/// Report something unexpected that generation recovered from, in the browser
+/// console or on stderr.
+pub fn warn(message: &str) {
+    #[cfg(target_arch = "wasm32")]
+    web_sys::console::warn_1(&message.into());
+    #[cfg(not(target_arch = "wasm32"))]
+    eprintln!("{}", message);
+}
+
+/// Hash a cell position with a seed. The result only depends on the seed and
+/// the absolute position of the cell, so it is stable across regeneration and
+/// chunk boundaries.
+pub fn hash_cell(seed: u64, row: usize, col: usize) -> u64 {
+    mix(mix(mix(seed) ^ row as u64) ^ col as u64)
+}
+
+/// Hash a seed string into a 64-bit seed (FNV-1a). Unlike the standard
+/// library hasher the result is stable across platforms and releases.
+pub fn hash_str(value: &str) -> u64 {
+    value.bytes().fold(0xcbf2_9ce4_8422_2325, |h, byte| {
+        (h ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Derive an independent seed for a generation stage, so stages don't share
+/// random streams and nearby seeds don't produce overlapping sub-seeds.
+pub fn derive_seed(seed: u64, stage: &str) -> u64 {
+    mix(mix(seed) ^ hash_str(stage))
+}
+
+// SplitMix64 finalizer
+fn mix(value: u64) -> u64 {
+    let mut h = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
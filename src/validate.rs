@@ -0,0 +1,474 @@
+use crate::atlas::Tile;
+use crate::map::{edge_slope, Map, Slope};
+use crate::settlement::footprint;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ViolationKind {
+  // A slope or edge tile that doesn't match the heights around it
+  SlopeMismatch(Tile),
+  // A slope or edge tile next to a drop of more than one level
+  SteepDrop(Tile),
+  // A road cell that doesn't continue to another road or a landmark
+  DisconnectedRoad(Tile),
+  // An object in the second layer that can't sit on the tile below it
+  MisplacedObject(Tile, Tile),
+  // A tile at a height its terrain can't have
+  HeightMismatch(Tile, u8),
+  // A lake cell next to lower ground the water would drain into
+  LeakingLake(u16),
+  // Part of a building whose other parts are missing
+  BrokenBuilding(Tile),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Violation {
+  pub row: usize,
+  pub col: usize,
+  pub kind: ViolationKind,
+}
+
+impl fmt::Display for Violation {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "({}, {}): ", self.row, self.col)?;
+    match self.kind {
+      ViolationKind::SlopeMismatch(tile) => {
+        write!(f, "{:?} doesn't match the surrounding heights", tile)
+      }
+      ViolationKind::SteepDrop(tile) => {
+        write!(f, "{:?} is next to a drop of more than one level", tile)
+      }
+      ViolationKind::DisconnectedRoad(tile) => write!(f, "{:?} is not connected", tile),
+      ViolationKind::MisplacedObject(tile_2, tile) => {
+        write!(f, "{:?} can't be placed on {:?}", tile_2, tile)
+      }
+      ViolationKind::HeightMismatch(tile, height) => {
+        write!(f, "{:?} can't be at height {}", tile, height)
+      }
+      ViolationKind::LeakingLake(id) => write!(f, "Lake {} drains into lower ground", id),
+      ViolationKind::BrokenBuilding(tile) => write!(f, "{:?} is part of an incomplete building", tile),
+    }
+  }
+}
+
+// Slope of a slope tile and whether it was found using all 8 neighbors
+fn tile_slope(tile: Tile) -> Option<(Slope, bool)> {
+  let slope = match tile {
+    Tile::BaseNorth | Tile::RoadSlopeNorth => (Slope::North, false),
+    Tile::BaseEast | Tile::RoadSlopeEast => (Slope::East, false),
+    Tile::BaseSouth | Tile::RoadSlopeSouth => (Slope::South, false),
+    Tile::BaseWest | Tile::RoadSlopeWest => (Slope::West, false),
+    Tile::RockNorth | Tile::MarshNorth | Tile::SandNorth => (Slope::North, true),
+    Tile::RockEast | Tile::MarshEast | Tile::SandEast => (Slope::East, true),
+    Tile::RockSouth | Tile::MarshSouth | Tile::SandSouth => (Slope::South, true),
+    Tile::RockWest | Tile::MarshWest | Tile::SandWest => (Slope::West, true),
+    Tile::RockNorthEast1 | Tile::MarshNorthEast1 | Tile::SandNorthEast1 => (Slope::NorthEast1, true),
+    Tile::RockSouthEast1 | Tile::MarshSouthEast1 | Tile::SandSouthEast1 => (Slope::SouthEast1, true),
+    Tile::RockSouthWest1 | Tile::MarshSouthWest1 | Tile::SandSouthWest1 => (Slope::SouthWest1, true),
+    Tile::RockNorthWest1 | Tile::MarshNorthWest1 | Tile::SandNorthWest1 => (Slope::NorthWest1, true),
+    Tile::RockNorthEast3 | Tile::MarshNorthEast3 | Tile::SandNorthEast3 => (Slope::NorthEast3, true),
+    Tile::RockSouthEast3 | Tile::MarshSouthEast3 | Tile::SandSouthEast3 => (Slope::SouthEast3, true),
+    Tile::RockSouthWest3 | Tile::MarshSouthWest3 | Tile::SandSouthWest3 => (Slope::SouthWest3, true),
+    Tile::RockNorthWest3 | Tile::MarshNorthWest3 | Tile::SandNorthWest3 => (Slope::NorthWest3, true),
+    Tile::RockSaddleNorthEast | Tile::MarshSaddleNorthEast | Tile::SandSaddleNorthEast => {
+      (Slope::SaddleNorthEast, true)
+    }
+    Tile::RockSaddleSouthEast | Tile::MarshSaddleSouthEast | Tile::SandSaddleSouthEast => {
+      (Slope::SaddleSouthEast, true)
+    }
+    _ => return None,
+  };
+  Some(slope)
+}
+
+fn cliff_slope(tile: Tile) -> Option<Slope> {
+  let slope = match tile {
+    Tile::Cliff => Slope::Unknown,
+    Tile::CliffNorth => Slope::North,
+    Tile::CliffEast => Slope::East,
+    Tile::CliffSouth => Slope::South,
+    Tile::CliffWest => Slope::West,
+    Tile::CliffNorthEast1 => Slope::NorthEast1,
+    Tile::CliffSouthEast1 => Slope::SouthEast1,
+    Tile::CliffSouthWest1 => Slope::SouthWest1,
+    Tile::CliffNorthWest1 => Slope::NorthWest1,
+    Tile::CliffNorthEast3 => Slope::NorthEast3,
+    Tile::CliffSouthEast3 => Slope::SouthEast3,
+    Tile::CliffSouthWest3 => Slope::SouthWest3,
+    Tile::CliffNorthWest3 => Slope::NorthWest3,
+    Tile::CliffSaddleNorthEast => Slope::SaddleNorthEast,
+    Tile::CliffSaddleSouthEast => Slope::SaddleSouthEast,
+    _ => return None,
+  };
+  Some(slope)
+}
+
+// Can an object in the second layer sit on a tile?
+fn can_hold(tile_2: Tile, tile: Tile) -> bool {
+  match tile_2 {
+    Tile::Tree | Tile::TreeAlt | Tile::Rocks | Tile::GrassPatch => tile == Tile::Base,
+    Tile::Reeds => tile.terrain() == Tile::Marsh,
+    Tile::WaterLily => tile == Tile::Water,
+    t if t.is_building() => tile == Tile::Base,
+    Tile::Ruin | Tile::RuinPillar | Tile::Tent | Tile::Shrine => tile == Tile::Base,
+    Tile::Campfire => tile == Tile::Base || tile == Tile::Sand,
+    t if t.is_tunnel() => matches!(tile.terrain(), Tile::Rock | Tile::Cliff),
+    t if t.is_bridge() => matches!(tile.terrain(), Tile::Water | Tile::WaterDeep | Tile::Marsh | Tile::Sand),
+    _ => true,
+  }
+}
+
+impl Map {
+  // Roads, and the bridges and tunnels that carry them
+  fn is_road_network(&self, row: usize, col: usize) -> bool {
+    let tile_2 = self.get_tile_2(row, col);
+    self.get_tile(row, col).is_road() || tile_2.is_bridge() || tile_2.is_tunnel()
+  }
+
+  // What a road can lead to: a rock landmark, or a settlement. Every building
+  // of a settlement counts as the same place.
+  fn road_destination(&self, row: usize, col: usize) -> Option<(usize, usize)> {
+    let tile_2 = self.get_tile_2(row, col);
+    if tile_2 == Tile::Rocks {
+      Some((row, col))
+    } else if tile_2.is_building() {
+      let settlement = self.settlements.iter().find(|settlement| settlement.contains(row, col));
+      Some(settlement.map_or((row, col), |settlement| settlement.center))
+    } else {
+      None
+    }
+  }
+
+  /// Connected pieces of the road network, with the number of landmarks and
+  /// settlements each one leads to. Every piece should link at least two.
+  pub(crate) fn road_networks(&self) -> Vec<(Vec<(usize, usize)>, usize)> {
+    let mut seen = vec![false; self.row_count * self.col_count];
+    let mut networks = Vec::new();
+    for row in 0..self.row_count {
+      for col in 0..self.col_count {
+        if seen[row * self.col_count + col] || !self.is_road_network(row, col) {
+          continue;
+        }
+        seen[row * self.col_count + col] = true;
+        let mut cells = vec![(row, col)];
+        let mut destinations = Vec::new();
+        let mut next = 0;
+        while next < cells.len() {
+          let (r, c) = cells[next];
+          next += 1;
+          for (n_row, n_col) in self.neighbors_4(r, c) {
+            if let Some(destination) = self.road_destination(n_row, n_col) {
+              if !destinations.contains(&destination) {
+                destinations.push(destination);
+              }
+            }
+            let n = n_row * self.col_count + n_col;
+            if !seen[n] && self.is_road_network(n_row, n_col) {
+              seen[n] = true;
+              cells.push((n_row, n_col));
+            }
+          }
+        }
+        networks.push((cells, destinations.len()));
+      }
+    }
+    networks
+  }
+
+  // Water is generated in the water bands, or where the cleanup of invalid
+  // tiles lowers a cell into a water band
+  fn is_water_height(&self, height: u8) -> bool {
+    matches!(self.config.level_tile(height), Tile::Water | Tile::WaterDeep)
+      || self.config.tile_heights(Tile::Water).contains(&height)
+      || self.config.tile_heights(Tile::WaterDeep).contains(&height)
+  }
+
+  /// Check the map for inconsistencies and report every violation found. A
+  /// generated map should have none.
+  pub fn validate(&self) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for row in 0..self.row_count {
+      for col in 0..self.col_count {
+        let mut report = |kind| violations.push(Violation { row, col, kind });
+        let tile = self.get_tile(row, col);
+        let tile_2 = self.get_tile_2(row, col);
+        let height = self.get_height(row, col);
+
+        // Snow lies on rock slopes without changing their shape
+        if let Some((slope, all_neighbors)) = tile_slope(tile.thawed()) {
+          let neighbors = if all_neighbors {
+            self.neighbors_8(row, col)
+          } else {
+            self.neighbors_4(row, col)
+          };
+          let lower = neighbors
+            .into_iter()
+            .filter(|n| self.get_height(n.0, n.1) < height)
+            .collect::<Vec<(usize, usize)>>();
+          if edge_slope((row, col), &lower) != slope {
+            report(ViolationKind::SlopeMismatch(tile));
+          }
+          // Slopes only ever step down one level
+          if lower.iter().any(|n| self.get_height(n.0, n.1) < height - 1) {
+            report(ViolationKind::SteepDrop(tile));
+          }
+        }
+
+        if let Some(slope) = cliff_slope(tile) {
+          let steep = self
+            .neighbors_8(row, col)
+            .into_iter()
+            .filter(|n| self.get_height(n.0, n.1) + 1 < height)
+            .collect::<Vec<(usize, usize)>>();
+          if steep.is_empty() || edge_slope((row, col), &steep) != slope {
+            report(ViolationKind::SlopeMismatch(tile));
+          }
+        }
+
+        if tile.is_road() {
+          let connections = self
+            .neighbors_4(row, col)
+            .into_iter()
+            .filter(|n| {
+              let tile_2 = self.get_tile_2(n.0, n.1);
+              self.get_tile(n.0, n.1).is_road()
+                || tile_2 == Tile::Rocks
+                || tile_2.is_building()
+                || tile_2.is_bridge()
+                || tile_2.is_tunnel()
+            })
+            .count();
+          if connections < 2 {
+            report(ViolationKind::DisconnectedRoad(tile));
+          }
+        }
+
+        if !can_hold(tile_2, tile) {
+          report(ViolationKind::MisplacedObject(tile_2, tile));
+        }
+        if let Some((parts, (d_row, d_col))) = footprint(tile_2) {
+          let is_complete = row >= d_row
+            && col >= d_col
+            && parts.iter().all(|&(p_row, p_col, part)| {
+              let (p_row, p_col) = (row - d_row + p_row, col - d_col + p_col);
+              p_row < self.row_count && p_col < self.col_count && self.get_tile_2(p_row, p_col) == part
+            });
+          if !is_complete {
+            report(ViolationKind::BrokenBuilding(tile_2));
+          }
+        }
+        if matches!(tile_2, Tile::Tree | Tile::TreeAlt) && height > self.config.tree_line_height() {
+          report(ViolationKind::HeightMismatch(tile_2, height));
+        }
+
+        let terrain = match tile.terrain() {
+          // Water left behind when invalid tiles are lowered
+          Tile::Water | Tile::WaterDeep => None,
+          // Beaches follow the water rather than the terrain bands
+          Tile::Base | Tile::Cliff | Tile::Sand => None,
+          terrain => Some(terrain),
+        };
+        if let Some(terrain) = terrain {
+          if !self.config.tile_heights(terrain).contains(&height) {
+            report(ViolationKind::HeightMismatch(tile, height));
+          }
+        }
+        let lake = self.get_lake(row, col);
+        if lake != 0 {
+          // Lakes are flat and held in by higher ground
+          if tile.thawed() != Tile::Water || self.lakes[lake as usize - 1].level != height {
+            report(ViolationKind::HeightMismatch(tile, height));
+          }
+          if self
+            .neighbors_4(row, col)
+            .iter()
+            .any(|n| self.get_height(n.0, n.1) < height)
+          {
+            report(ViolationKind::LeakingLake(lake));
+          }
+        } else if matches!(tile, Tile::Water | Tile::WaterDeep) && !self.is_water_height(height) {
+          report(ViolationKind::HeightMismatch(tile, height));
+        }
+      }
+    }
+
+    // Roads that don't lead anywhere, like a loop or a road from a landmark
+    // that never reaches a second one, reported once at their first cell
+    for (cells, destinations) in self.road_networks() {
+      if destinations < 2 {
+        let (row, col) = cells[0];
+        let tile = self.get_tile(row, col);
+        let tile = if tile.is_road() { tile } else { self.get_tile_2(row, col) };
+        violations.push(Violation {
+          row,
+          col,
+          kind: ViolationKind::DisconnectedRoad(tile),
+        });
+      }
+    }
+    violations
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Violation, ViolationKind};
+  use crate::atlas::Tile;
+  use crate::config::GenerationConfig;
+  use crate::map::Map;
+  use crate::shape::Shape;
+
+  #[test]
+  fn generated_maps_are_valid() {
+    for seed in 0..1000 {
+      let mut map = Map::new(32, 48);
+      map.generate(seed, &GenerationConfig::default());
+      assert_eq!(map.validate(), vec![], "seed {}", seed);
+    }
+  }
+
+  #[test]
+  fn maps_with_more_levels_are_valid() {
+    let config = GenerationConfig {
+      height_levels: 10,
+      ..GenerationConfig::default()
+    };
+    for seed in 0..100 {
+      let mut map = Map::new(32, 48);
+      map.generate(seed, &config);
+      assert!(map.height_map.iter().any(|&height| height > 4));
+      assert_eq!(map.validate(), vec![], "seed {}", seed);
+    }
+  }
+
+  #[test]
+  fn shaped_maps_are_valid() {
+    for &shape in &[Shape::Island, Shape::Archipelago, Shape::Continent, Shape::Inland] {
+      let config = GenerationConfig {
+        shape,
+        ..GenerationConfig::default()
+      };
+      for seed in 0..50 {
+        let mut map = Map::new(48, 48);
+        map.generate(seed, &config);
+        assert_eq!(map.validate(), vec![], "{:?} seed {}", shape, seed);
+      }
+    }
+  }
+
+  #[test]
+  fn finds_slope_mismatch() {
+    let mut map = Map::new(3, 3);
+    #[rustfmt::skip]
+    let height_map = vec![
+      1, 1, 1,
+      3, 3, 3,
+      3, 3, 3,
+    ];
+    map.height_map = height_map;
+    map.tile_map[4] = Tile::BaseNorth;
+    map.tile_map[5] = Tile::BaseSouth;
+    assert_eq!(
+      map.validate(),
+      vec![
+        Violation {
+          row: 1,
+          col: 1,
+          kind: ViolationKind::SteepDrop(Tile::BaseNorth)
+        },
+        Violation {
+          row: 1,
+          col: 2,
+          kind: ViolationKind::SlopeMismatch(Tile::BaseSouth)
+        },
+        Violation {
+          row: 1,
+          col: 2,
+          kind: ViolationKind::SteepDrop(Tile::BaseSouth)
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn finds_steep_drops() {
+    // A slope down one level
+    let mut map = Map::new(3, 3);
+    map.height_map = vec![2, 2, 2, 3, 3, 3, 3, 3, 3];
+    for col in 0..3 {
+      map.set_tile(1, col, Tile::BaseNorth);
+    }
+    assert_eq!(map.validate(), vec![]);
+    // The same slope down two levels needs a cliff
+    map.set_height(0, 1, 1);
+    let kinds = map.validate().iter().map(|v| v.kind).collect::<Vec<ViolationKind>>();
+    assert_eq!(kinds, vec![ViolationKind::SteepDrop(Tile::BaseNorth)]);
+  }
+
+  #[test]
+  fn finds_misplaced_objects_and_roads() {
+    let mut map = Map::new(3, 3);
+    map.height_map = vec![2; 9];
+    map.tile_map[0] = Tile::Water;
+    map.tile_map_2[0] = Tile::Tree;
+    map.tile_map[4] = Tile::Road1;
+    map.tile_map[5] = Tile::Road2;
+    map.tile_map_2[3] = Tile::Rocks;
+    assert_eq!(
+      map.validate(),
+      vec![
+        Violation {
+          row: 0,
+          col: 0,
+          kind: ViolationKind::MisplacedObject(Tile::Tree, Tile::Water)
+        },
+        Violation {
+          row: 0,
+          col: 0,
+          kind: ViolationKind::HeightMismatch(Tile::Water, 2)
+        },
+        Violation {
+          row: 1,
+          col: 2,
+          kind: ViolationKind::DisconnectedRoad(Tile::Road2)
+        },
+        // The road only reaches one landmark
+        Violation {
+          row: 1,
+          col: 1,
+          kind: ViolationKind::DisconnectedRoad(Tile::Road1)
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn finds_roads_between_nowhere() {
+    // A road from one landmark to another, and a loop leading nowhere
+    let mut map = Map::new(6, 6);
+    map.height_map = vec![2; 36];
+    map.tile_map_2[0] = Tile::Rocks;
+    map.tile_map[1] = Tile::Road2;
+    map.tile_map[2] = Tile::Road2;
+    map.tile_map_2[3] = Tile::Rocks;
+    #[rustfmt::skip]
+    let loop_tiles = [
+      (3, 2, Tile::RoadSouthEast), (3, 3, Tile::Road2), (3, 4, Tile::RoadSouthWest),
+      (4, 2, Tile::Road1), (4, 4, Tile::Road1),
+      (5, 2, Tile::RoadNorthEast), (5, 3, Tile::Road2), (5, 4, Tile::RoadNorthWest),
+    ];
+    for &(row, col, tile) in loop_tiles.iter() {
+      map.set_tile(row, col, tile);
+    }
+    assert_eq!(
+      map.validate(),
+      vec![Violation {
+        row: 3,
+        col: 2,
+        kind: ViolationKind::DisconnectedRoad(Tile::RoadSouthEast)
+      }]
+    );
+    let networks = map.road_networks();
+    assert_eq!(networks.iter().map(|(_, links)| *links).collect::<Vec<usize>>(), vec![2, 0]);
+  }
+}
@@ -0,0 +1,129 @@
+use crate::config::{GenerationConfig, GenerationMode};
+use crate::map::GENERATOR_VERSION;
+use crate::shape::Shape;
+use std::fmt;
+
+// Codes start with this and the generator version, which changes whenever
+// the same code would make a different world
+const PREFIX: &str = "ww";
+// Largest number of rows or columns a code can ask for, so a shared code
+// can't make generation run out of memory
+const MAX_SIZE: usize = 1024;
+
+/// A shareable code that reproduces a world: seed, size and generation config.
+///
+/// Codes look like `ww21-00000000000000ff-128x128`, where 21 is the generator
+/// version, followed by any settings that differ from the defaults, e.g.
+/// `ww21-00000000000000ff-128x128-ls:20`. Codes from other versions are
+/// rejected, as they would no longer make the world they were shared for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorldCode {
+  pub seed: u64,
+  pub row_count: usize,
+  pub col_count: usize,
+  pub config: GenerationConfig,
+}
+
+impl fmt::Display for WorldCode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "{}{}-{:016x}-{}x{}",
+      PREFIX, GENERATOR_VERSION, self.seed, self.row_count, self.col_count
+    )?;
+    for pair in self.config.encode() {
+      write!(f, "-{}", pair)?;
+    }
+    Ok(())
+  }
+}
+
+impl WorldCode {
+  pub fn parse(code: &str) -> Result<WorldCode, String> {
+    let mut parts = code.trim().split('-');
+    let version = parts
+      .next()
+      .and_then(|s| s.strip_prefix(PREFIX))
+      .and_then(|s| s.parse::<u32>().ok())
+      .ok_or("Unsupported world code")?;
+    if version != GENERATOR_VERSION {
+      return Err(format!(
+        "World code is from generator version {} and won't reproduce its world in version {}",
+        version, GENERATOR_VERSION
+      ));
+    }
+    let seed = parts
+      .next()
+      .and_then(|s| u64::from_str_radix(s, 16).ok())
+      .ok_or("Invalid seed")?;
+    let (row_count, col_count) = parts
+      .next()
+      .and_then(|s| s.split_once('x'))
+      .and_then(|(rows, cols)| Some((rows.parse().ok()?, cols.parse().ok()?)))
+      .filter(|&(rows, cols)| rows > 0 && cols > 0 && rows <= MAX_SIZE && cols <= MAX_SIZE)
+      .ok_or("Invalid size")?;
+    let mut config = GenerationConfig::default();
+    for pair in parts {
+      let (key, value) = pair.split_once(':').ok_or("Invalid setting")?;
+      config.decode(key, value)?;
+    }
+    // Wave function collapse doesn't use the shape mask
+    if config.mode == GenerationMode::Collapse && config.shape != Shape::Open {
+      return Err("Shapes only apply to noise terrain".to_string());
+    }
+    Ok(WorldCode {
+      seed,
+      row_count,
+      col_count,
+      config,
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::WorldCode;
+  use crate::config::GenerationConfig;
+  use crate::map::GENERATOR_VERSION;
+
+  #[test]
+  fn round_trip() {
+    let code = WorldCode {
+      seed: u64::MAX - 1,
+      row_count: 64,
+      col_count: 128,
+      config: GenerationConfig {
+        patch_spacing: 12.0,
+        ..GenerationConfig::default()
+      },
+    };
+    assert_eq!(
+      code.to_string(),
+      format!("ww{}-fffffffffffffffe-64x128-ps:12", GENERATOR_VERSION)
+    );
+    assert_eq!(WorldCode::parse(&code.to_string()), Ok(code));
+  }
+
+  #[test]
+  fn invalid_codes() {
+    let parse = |rest: &str| WorldCode::parse(&format!("ww{}-{}", GENERATOR_VERSION, rest));
+    assert!(WorldCode::parse("").is_err());
+    assert!(parse("zz-64x64").is_err());
+    assert!(parse("ff-0x64").is_err());
+    assert!(parse("ff-100000x100000").is_err());
+    assert!(parse("ff-64x1025").is_err());
+    assert!(parse("ff-1024x1024").is_ok());
+    assert!(parse("ff-64x64-nope:1").is_err());
+    assert!(parse("ff-64x64-gm:1-sh:1").is_err());
+    assert!(parse("ff-64x64-sh:1").is_ok());
+  }
+
+  #[test]
+  fn codes_from_other_versions() {
+    assert!(WorldCode::parse("ww-ff-64x64").is_err());
+    assert!(WorldCode::parse("wwx-ff-64x64").is_err());
+    assert!(WorldCode::parse("ww1-ff-64x64").is_err());
+    let newer = format!("ww{}-ff-64x64", GENERATOR_VERSION + 1);
+    assert!(WorldCode::parse(&newer).unwrap_err().contains("version"));
+  }
+}
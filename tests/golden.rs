@@ -0,0 +1,132 @@
+//! Golden output tests for world generation.
+//!
+//! Saved seeds must keep producing the same worlds. If generation changes on
+//! purpose, bump `GENERATOR_VERSION` and update `GOLDEN_VERSION` and the
+//! hashes below (the failure message prints the new values).
+
+use wide_world::{Game, GenerationMode, Shape, GENERATOR_VERSION};
+
+const GOLDEN_VERSION: u32 = 22;
+
+const SEEDS: &[&str] = &["0", "1", "255", "wide world", "4294967295"];
+const SIZES: &[(usize, usize)] = &[(16, 16), (64, 64), (48, 96), (128, 128)];
+
+// (seed, rows, cols, tile_map, tile_map_2, height_map)
+#[rustfmt::skip]
+const GOLDEN: &[(&str, usize, usize, u64, u64, u64)] = &[
+  ("0", 16, 16, 0x81a0f31ad98852f3, 0x6f7ac13bc860e3d8, 0xa74302d32a24981e),
+  ("0", 64, 64, 0x0b0154690a7de994, 0x3f9b90c6f9c3a8d2, 0xa72b755c2534e657),
+  ("0", 48, 96, 0xe84ec20eab6ea127, 0x7ddad6e08095fb3d, 0x2f518afa06cb314b),
+  ("0", 128, 128, 0xba2244dcfb016498, 0x865ba4197507b6e6, 0x17d3e6dd4bbd12b1),
+  ("1", 16, 16, 0x641ed311bfc621b3, 0x2ab5722fcf956049, 0xc939c7929680994d),
+  ("1", 64, 64, 0x853e166706340cee, 0x5f609a2c3d46e39e, 0x1f35e670a5eb46ac),
+  ("1", 48, 96, 0xbe1ad7812e7ddcdc, 0x14ce5f0a89d2a6ae, 0xd9e2d6293d367ebc),
+  ("1", 128, 128, 0x70b9fb62d205829b, 0x3cfbf07fa912c609, 0x04aff66b8c4da08e),
+  ("255", 16, 16, 0x94424476caa78fb1, 0xaba1c41f9de01dd1, 0xc555524c1b1b254b),
+  ("255", 64, 64, 0xd331166c169870e1, 0x58e70d757b0e1c38, 0xfbc17f9959c35527),
+  ("255", 48, 96, 0xa9715b6f7a826613, 0x4912a09f6f8fd02c, 0x43816d4804ef7268),
+  ("255", 128, 128, 0x1a9c5c700d05c272, 0x7f3c60b8882b2b3a, 0x41d4ce3e4d08d422),
+  ("wide world", 16, 16, 0xa6a492ce97d3ca28, 0x5d9847fcb97365da, 0x796ebba13a5b744f),
+  ("wide world", 64, 64, 0x1aa0ef20f5801558, 0xdef2a6e9481c98c0, 0x87e20620c5576036),
+  ("wide world", 48, 96, 0x9f1bc10b9f9da948, 0x02f54c1b6751bee4, 0x7026e5c04c2c4364),
+  ("wide world", 128, 128, 0xbe606a9c4ac75f86, 0x869bd4d4e03178ec, 0x88142c858c7d7f2e),
+  ("4294967295", 16, 16, 0x8ffcc27781f9f603, 0xa94219d3309e9b5f, 0x1b64ef95000e5562),
+  ("4294967295", 64, 64, 0xbabd7c4141cbffc9, 0x765bf57d464d74c2, 0x4211821d31e44454),
+  ("4294967295", 48, 96, 0xcbf1f04547803162, 0x7610dc1d108aaea5, 0x725a90f4bb1d0b0b),
+  ("4294967295", 128, 128, 0x6efcb3115b8e83b8, 0xb6782d26a3fd89b5, 0x896d9d399233ed0d),
+];
+
+// Worlds from codes covering the collapse mode, every shape and settings
+// that differ from the defaults, as (code, tile_map, tile_map_2, height_map).
+// The codes leave out the `ww<version>-` they start with.
+#[rustfmt::skip]
+const GOLDEN_CODES: &[(&str, u64, u64, u64)] = &[
+  ("00000000000000ff-64x64-gm:1", 0x107c203c3ed51d2d, 0x0b5e153db9c099b8, 0xfe708239604d60a0),
+  ("00000000000000ff-64x64-sh:1", 0x0aaead48b7312ec5, 0x19cb7923c8824210, 0xd2ef3fa38d61deeb),
+  ("00000000000000ff-64x64-sh:2", 0x21125786dfd4fbed, 0x77df0edab7a224dc, 0x1f4880598e92dde6),
+  ("00000000000000ff-64x64-sh:3", 0xa14ed6a906fd9f78, 0xdd2615e336cfa618, 0x96dcc6710103f091),
+  ("00000000000000ff-64x64-sh:4", 0x38f05f9eab443f3b, 0x8df2df724dd8ce50, 0x67104801c5d5729e),
+  ("0000000000000001-48x96-ws:48-hl:8-ls:20-st:5", 0x53493f57ff2e6350, 0x093a297926d919df, 0x68d9f83b39658f66),
+];
+
+fn hash(bytes: impl Iterator<Item = u8>) -> u64 {
+  bytes.fold(0xcbf2_9ce4_8422_2325, |h, byte| {
+    (h ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+  })
+}
+
+fn layer_hashes(game: &Game) -> (u64, u64, u64) {
+  (
+    hash(game.tile_map().iter().map(|&t| t as u8)),
+    hash(game.tile_map_2().iter().map(|&t| t as u8)),
+    hash(game.height_map().iter().cloned()),
+  )
+}
+
+#[test]
+fn generator_version_matches_golden_values() {
+  assert_eq!(
+    GENERATOR_VERSION, GOLDEN_VERSION,
+    "Generator version changed, update the golden values"
+  );
+}
+
+#[test]
+fn generation_matches_golden_values() {
+  let mut actual = Vec::new();
+  for &seed in SEEDS {
+    for &(rows, cols) in SIZES {
+      let (tiles, tiles_2, heights) = layer_hashes(&Game::new(rows, cols, seed, Shape::Open, GenerationMode::Noise));
+      actual.push((seed, rows, cols, tiles, tiles_2, heights));
+    }
+  }
+  let lines = actual
+    .iter()
+    .map(|(seed, rows, cols, tiles, tiles_2, heights)| {
+      format!(
+        "  ({:?}, {}, {}, 0x{:016x}, 0x{:016x}, 0x{:016x}),",
+        seed, rows, cols, tiles, tiles_2, heights
+      )
+    })
+    .collect::<Vec<String>>()
+    .join("\n");
+  assert!(
+    actual == GOLDEN,
+    "Generated worlds don't match the golden values:\n{}",
+    lines
+  );
+}
+
+#[test]
+fn codes_match_golden_values() {
+  let actual = GOLDEN_CODES
+    .iter()
+    .map(|&(code, ..)| {
+      let full_code = format!("ww{}-{}", GENERATOR_VERSION, code);
+      let game = Game::from_code(&full_code).unwrap_or_else(|_| panic!("Invalid code {}", full_code));
+      let (tiles, tiles_2, heights) = layer_hashes(&game);
+      (code, tiles, tiles_2, heights)
+    })
+    .collect::<Vec<(&str, u64, u64, u64)>>();
+  let lines = actual
+    .iter()
+    .map(|(code, tiles, tiles_2, heights)| {
+      format!("  ({:?}, 0x{:016x}, 0x{:016x}, 0x{:016x}),", code, tiles, tiles_2, heights)
+    })
+    .collect::<Vec<String>>()
+    .join("\n");
+  assert!(
+    actual == GOLDEN_CODES,
+    "Worlds from codes don't match the golden values:\n{}",
+    lines
+  );
+}
+
+#[test]
+fn generation_is_repeatable() {
+  for &seed in SEEDS {
+    let a = Game::new(64, 64, seed, Shape::Open, GenerationMode::Noise);
+    let b = Game::new(64, 64, seed, Shape::Open, GenerationMode::Noise);
+    assert_eq!(layer_hashes(&a), layer_hashes(&b));
+  }
+}
@@ -0,0 +1,43 @@
+//! Custom generation passes, plugged in from outside the crate
+
+use wide_world::{Game, GenerationConfig, GenerationContext, GenerationMode, GenerationPass, Pipeline, Shape, Tile};
+
+// Clears the trees the forest pass grew
+struct Logging;
+
+impl GenerationPass for Logging {
+  fn name(&self) -> &str {
+    "logging"
+  }
+
+  fn run(&self, context: &mut GenerationContext) {
+    for row in 0..context.map.row_count {
+      for col in 0..context.map.col_count {
+        if matches!(context.map.get_tile_2(row, col), Tile::Tree | Tile::TreeAlt) {
+          context.map.set_tile_2(row, col, Tile::Empty);
+        }
+      }
+    }
+  }
+}
+
+#[test]
+fn default_pipeline_matches_game() {
+  let game = Game::new(48, 48, "pipeline", Shape::Open, GenerationMode::Noise);
+  let custom = Game::with_pipeline(48, 48, "pipeline", GenerationConfig::default(), &Pipeline::default());
+  assert_eq!(game.tile_map(), custom.tile_map());
+  assert_eq!(game.tile_map_2(), custom.tile_map_2());
+  assert_eq!(game.height_map(), custom.height_map());
+}
+
+#[test]
+fn custom_passes_run_in_order() {
+  let mut pipeline = Pipeline::default();
+  pipeline.insert_after("forests", Logging).unwrap();
+  let game = Game::with_pipeline(48, 48, "pipeline", GenerationConfig::default(), &pipeline);
+  assert!(game
+    .tile_map_2()
+    .iter()
+    .all(|&tile| tile != Tile::Tree && tile != Tile::TreeAlt));
+  assert!(game.js_validate().is_empty());
+}
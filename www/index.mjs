@@ -20,7 +20,7 @@ const INITIAL_COORD = toIso({
   x: (DEFAULT_COL_COUNT * CELL_SIZE) / 2,
   y: (DEFAULT_ROW_COUNT * CELL_SIZE) / 2,
 });
-const DEFAULT_SEED = 255;
+const DEFAULT_SEED = "255";
 
 const worker = new Worker("./worker.mjs", { type: "module" });
 
@@ -52,16 +52,21 @@ function init() {
     const pointer = initPointerEvents(canvas.el);
     const els = {
       loading: document.getElementById("loading"),
+      error: document.getElementById("error"),
       size: document.getElementById("size"),
       seed: document.getElementById("seed"),
     };
     const state = {
       lastTickTime: 0,
       level: undefined,
+      // Shown again if the next level fails to load
+      previousLevel: undefined,
     };
     const load = (rows, cols, seed) => {
       els.size.disabled = true;
       els.seed.disabled = true;
+      els.error.classList.add("hidden");
+      state.previousLevel = state.level;
       state.level = undefined;
       worker.postMessage({
         type: "load",
@@ -130,8 +135,7 @@ function init() {
     });
 
     els.seed.addEventListener("change", async (e) => {
-      const seed = clamp(parseInt(e.target.value, 10), 0, 4294967295);
-      e.target.value = seed;
+      const seed = e.target.value.trim();
       e.target.blur();
       load(DEFAULT_ROW_COUNT, DEFAULT_COL_COUNT, seed);
     });
@@ -205,6 +209,12 @@ function init() {
         });
         camera.moveTo(center.x, center.y);
         camera.zoomToCenter(1);
+      } else if (type === "error") {
+        state.level = state.previousLevel;
+        els.size.disabled = false;
+        els.seed.disabled = false;
+        els.error.textContent = rest.message;
+        els.error.classList.remove("hidden");
       }
     });
 
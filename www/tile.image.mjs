@@ -5,8 +5,9 @@ const VERTS_PER_QUAD = 6;
 const FLOATS_PER_VERT = 2;
 const INDICES = [0, 1, 3, 3, 1, 2];
 const SPRITES_PER_ROW = 12;
+const SPRITE_ROWS = 12;
 const TEX_X_OFFSET = 1 / SPRITES_PER_ROW;
-const TEX_Y_OFFSET = 1 / 5;
+const TEX_Y_OFFSET = 1 / SPRITE_ROWS;
 
 const vs = glsl`
   precision highp float;
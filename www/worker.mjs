@@ -1,10 +1,21 @@
-import init, { Game, Tile } from "./pkg/wide_world.js";
+import init, { Game, GenerationMode, Shape, Tile } from "./pkg/wide_world.js";
 
 init().then(({ memory }) => {
   let _game;
 
-  function load(rows, cols, seed) {
-    _game = Game.new(rows, cols, seed);
+  function load(
+    rows,
+    cols,
+    seed,
+    shape = Shape.Open,
+    mode = GenerationMode.Noise
+  ) {
+    // Seeds can also be world codes, which include the map size
+    _game = /^ww\d+-/.test(seed)
+      ? Game.fromCode(seed)
+      : Game.new(rows, cols, seed, shape, mode);
+    const code = _game.worldCode();
+    [rows, cols] = code.split("-")[2].split("x").map(Number);
     const tileMap1 = Array.from(
       new Uint8Array(memory.buffer, _game.tileMapPtr(), rows * cols)
     );
@@ -14,13 +25,20 @@ init().then(({ memory }) => {
     const heightMap = Array.from(
       new Uint8Array(memory.buffer, _game.heightMapPtr(), rows * cols)
     );
+    const lakeMap = Array.from(
+      new Uint16Array(memory.buffer, _game.lakeMapPtr(), rows * cols)
+    );
+    const lakeLevels = Array.from(_game.lakeLevels());
     return {
       tileMap1,
       tileMap2,
       heightMap,
+      lakeMap,
+      lakeLevels,
       rows,
       cols,
       seed,
+      code,
     };
   }
 
@@ -33,13 +51,18 @@ init().then(({ memory }) => {
   // }
 
   onmessage = ({ data }) => {
-    const { rows, cols, seed } = data;
+    const { rows, cols, seed, shape, mode } = data;
     switch (data.type) {
       case "load": {
-        postMessage({
-          type: "load",
-          ...load(rows, cols, seed),
-        });
+        // Bad world codes throw, report them rather than losing the worker
+        try {
+          postMessage({
+            type: "load",
+            ...load(rows, cols, seed, shape, mode),
+          });
+        } catch (e) {
+          postMessage({ type: "error", message: String(e) });
+        }
         break;
       }
       default: